@@ -0,0 +1,285 @@
+use crate::fresh_ident;
+use crate::parse::Args;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::Token;
+
+/// The input of `spez_matches!`: an expression, and the bounds to check for
+/// its type.
+pub struct MatchesArgs {
+	pub expr: syn::Expr,
+	pub bounds: Punctuated<syn::TypeParamBound, Token![+]>,
+}
+
+impl syn::parse::Parse for MatchesArgs {
+	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+		let expr = input.parse()?;
+		input.parse::<Token![,]>()?;
+		let bounds = Punctuated::parse_separated_nonempty(input)?;
+		input.parse::<Option<Token![,]>>()?;
+		Ok(Self { expr, bounds })
+	}
+}
+
+/// The input of `spez_all_matches!`: an expression, and a list of bounds to
+/// check for its type.
+pub struct AllMatchesArgs {
+	pub expr: syn::Expr,
+	pub bounds: Punctuated<Punctuated<syn::TypeParamBound, Token![+]>, Token![,]>,
+}
+
+impl syn::parse::Parse for AllMatchesArgs {
+	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+		let expr = input.parse()?;
+		input.parse::<Token![,]>()?;
+		let content;
+		let bracket = syn::bracketed!(content in input);
+		let bounds = content.parse_terminated(Punctuated::parse_separated_nonempty, Token![,])?;
+		if bounds.is_empty() {
+			return Err(syn::Error::new(
+				bracket.span.join(),
+				"expected at least one bound",
+			));
+		}
+		input.parse::<Option<Token![,]>>()?;
+		Ok(Self { expr, bounds })
+	}
+}
+
+impl AllMatchesArgs {
+	/// Converts this into the arguments of one `spez_matches!` per bound, each
+	/// checking the value behind `name`.
+	pub fn into_args(self, name: &syn::Ident) -> syn::Result<Vec<Args>> {
+		self.bounds
+			.into_iter()
+			.map(|bounds| {
+				let expr = syn::parse_quote! { *#name };
+				MatchesArgs { expr, bounds }.into_args()
+			})
+			.collect()
+	}
+}
+
+impl MatchesArgs {
+	/// Converts this into the arguments of the equivalent `spez!`.
+	///
+	/// The input is borrowed, so it is not consumed.
+	pub fn into_args(self) -> syn::Result<Args> {
+		let expr = self.expr;
+		let bounds = self.bounds;
+		let ty = fresh_ident(quote! { #bounds }, "__Spez");
+		internal_args(quote! {
+			for &(#expr);
+			match<#ty: #bounds> &#ty -> bool { true }
+			else -> bool { false }
+		})
+	}
+
+	/// Converts this into the arguments of a `spez!` that fails to compile if
+	/// the bounds are not satisfied.
+	///
+	/// The error is reported when the `else` arm is instantiated, which only
+	/// happens if the first arm doesn't match.
+	pub fn into_assert_args(self) -> syn::Result<Args> {
+		let expr = self.expr;
+		let bounds = self.bounds;
+		let ty = fresh_ident(quote! { #bounds }, "__Spez");
+		internal_args(quote! {
+			for &(#expr);
+			match<#ty: #bounds> &#ty {}
+			else {
+				const {
+					::core::panic!(::core::concat!(
+						"the type of the expression does not implement `",
+						::core::stringify!(#bounds),
+						"`",
+					))
+				}
+			}
+		})
+	}
+}
+
+/// Parses the arguments of a `spez!` that a helper macro generates.
+///
+/// Its arms are not written by the user, so they don't count towards
+/// `SPEZ_MAX_ARMS`.
+fn internal_args(tokens: proc_macro2::TokenStream) -> syn::Result<Args> {
+	let mut args: Args = syn::parse2(tokens)?;
+	args.arm_spans.clear();
+	Ok(args)
+}
+
+/// Returns the arguments of a `spez!` that formats `expr` using its `Debug`
+/// implementation, or returns the name of its type otherwise.
+#[cfg(feature = "std")]
+pub fn type_name_args(expr: syn::Expr) -> syn::Result<Args> {
+	let name = fresh_ident(quote! { #expr }, "__spez_value");
+	let ty = fresh_ident(quote! { #expr }, "__Spez");
+	internal_args(quote! {
+		for #name = &(#expr);
+		match<#ty: ::core::fmt::Debug> &#ty -> ::std::string::String {
+			::std::format!("{:?}", #name)
+		}
+		match<#ty> &#ty -> ::std::string::String {
+			::std::string::String::from(::core::any::type_name::<#ty>())
+		}
+	})
+}
+
+/// Returns the arguments of a `spez!` that clones the value of `expr` if its
+/// type implements `Clone`.
+pub fn try_clone_args(expr: syn::Expr) -> syn::Result<Args> {
+	let name = fresh_ident(quote! { #expr }, "__spez_value");
+	let ty = fresh_ident(quote! { #expr }, "__Spez");
+	internal_args(quote! {
+		for #name = &(#expr);
+		match<#ty: ::core::clone::Clone> &#ty -> ::core::option::Option<#ty> {
+			::core::option::Option::Some(::core::clone::Clone::clone(#name))
+		}
+		match<#ty> &#ty -> ::core::option::Option<#ty> {
+			::core::option::Option::None
+		}
+	})
+}
+
+/// Converts the arguments of `spez_ref!` into those of the equivalent `spez!`.
+///
+/// The input is borrowed, and the `&` is added to the types of all arms, as
+/// with `for ref name = expr;`. An input that is already borrowed is left as
+/// it is.
+///
+/// A tuple of variables, like `for (a, b);`, is an error, as building the
+/// tuple would move them.
+pub fn ref_args(mut args: Args) -> syn::Result<Args> {
+	if let syn::Expr::Tuple(tuple) = &args.expr {
+		if args.each.is_none() && args.borrow.is_none() && tuple.elems.iter().any(is_place) {
+			return Err(syn::Error::new_spanned(
+				tuple,
+				"spez_ref! can not borrow a tuple of variables, as building the tuple moves \
+				 them; borrow each one with spez!, like `for (a, b) = (&a, &b);`",
+			));
+		}
+	}
+	if let (Some(in_token), syn::Expr::Array(array)) = (&args.each, &mut args.expr) {
+		let and_token = Token![&](in_token.span);
+		for expr in &mut array.elems {
+			*expr = syn::parse_quote! { #and_token (#expr) };
+		}
+		args.borrow = Some((and_token, None));
+	} else if args.borrow.is_none() {
+		let and_token = Token![&](args.expr.span());
+		let expr = &args.expr;
+		args.expr = syn::parse_quote! { #and_token (#expr) };
+		args.borrow = Some((and_token, None));
+	}
+	Ok(args)
+}
+
+/// Checks whether an expression names a place that would be moved out of.
+fn is_place(expr: &syn::Expr) -> bool {
+	match expr {
+		syn::Expr::Path(_) | syn::Expr::Field(_) | syn::Expr::Index(_) => true,
+		syn::Expr::Paren(paren) => is_place(&paren.expr),
+		syn::Expr::Tuple(tuple) => tuple.elems.iter().any(is_place),
+		_ => false,
+	}
+}
+
+/// Generates the `macro_rules!` for `#[derive(Spez)]`, which wraps a value in
+/// the variant of the enum that holds its type.
+pub fn derive_spez(input: syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+	let syn::Data::Enum(data) = &input.data else {
+		return Err(syn::Error::new_spanned(
+			&input.ident,
+			"`#[derive(Spez)]` can only be used on an enum",
+		));
+	};
+	if !input.generics.params.is_empty() {
+		return Err(syn::Error::new_spanned(
+			&input.generics,
+			"`#[derive(Spez)]` can not be used on a generic enum",
+		));
+	}
+	let mut name = None;
+	let mut krate = None;
+	for attr in input
+		.attrs
+		.iter()
+		.filter(|attr| attr.path().is_ident("spez"))
+	{
+		attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("name") {
+				let lit: syn::LitStr = meta.value()?.parse()?;
+				name = Some(lit.parse::<syn::Ident>()?);
+				Ok(())
+			} else if meta.path.is_ident("crate") {
+				krate = Some(meta.value()?.parse::<syn::Path>()?);
+				Ok(())
+			} else {
+				Err(meta.error("unknown spez option"))
+			}
+		})?;
+	}
+	let enum_name = &input.ident;
+	let name = name
+		.unwrap_or_else(|| syn::Ident::new(&snake_case(&enum_name.to_string()), enum_name.span()));
+	let krate = krate.unwrap_or_else(|| syn::parse_quote! { ::spez });
+	let value = syn::Ident::new("value", proc_macro2::Span::call_site());
+	let return_type = quote! { ::core::option::Option<#enum_name> };
+	let mut tys = Vec::new();
+	let arms = data
+		.variants
+		.iter()
+		.map(|variant| {
+			let ty = match &variant.fields {
+				syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0].ty,
+				_ => return Err(syn::Error::new_spanned(
+					variant,
+					"`#[derive(Spez)]` requires every variant to have exactly one unnamed field",
+				)),
+			};
+			let key = quote! { #ty }.to_string();
+			if tys.contains(&key) {
+				return Err(syn::Error::new_spanned(
+					ty,
+					"this type is already held by an earlier variant",
+				));
+			}
+			tys.push(key);
+			let variant = &variant.ident;
+			Ok(quote! {
+				match #ty -> #return_type {
+					::core::option::Option::Some(#enum_name::#variant(#value))
+				}
+			})
+		})
+		.collect::<syn::Result<Vec<_>>>()?;
+	Ok(quote! {
+		#[allow(unused_macros)]
+		macro_rules! #name {
+			($value:expr) => {
+				#krate::spez! {
+					for #value = $value;
+					#(#arms)*
+					else -> #return_type { ::core::option::Option::None }
+				}
+			};
+		}
+	})
+}
+
+/// Converts a name like `MyEnum` into `my_enum`.
+fn snake_case(name: &str) -> String {
+	let mut result = String::new();
+	let mut prev_lowercase = false;
+	for c in name.chars() {
+		if c.is_uppercase() && prev_lowercase {
+			result.push('_');
+		}
+		prev_lowercase = c.is_lowercase() || c.is_ascii_digit();
+		result.extend(c.to_lowercase());
+	}
+	result
+}
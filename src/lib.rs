@@ -28,6 +28,19 @@
 //! (See [the example below](#in-a-macro) for a demonstration of
 //! that.)
 //!
+//! The selected arm is called as a trait method, which is not possible in
+//! `const` contexts on stable Rust. Therefore, the macro can not be used in a
+//! `const fn` or in the initializer of a `const` or `static`:
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! const X: i32 = spez! {
+//!     for 1;
+//!     match i32 -> i32 { 1 }
+//!     else -> i32 { 0 }
+//! }; // ERROR
+//! ```
+//!
 //! # How to use it
 //!
 //! The basic syntax of the macro is:
@@ -35,9 +48,13 @@
 //! ```text
 //! spez! {
 //!     for <expression>;
+//!     [capture(<name>: <type>, ...);]
+//!     [prelude { <statements> }]
+//!     [type <name> = <type>; ...]
 //!     match <type> { <body> }
 //!     [match <type> { <body> }]
 //!     [...]
+//!     [else { <body> }]
 //! }
 //! ```
 //!
@@ -62,6 +79,9 @@
 //! }
 //! ```
 //!
+//! Any type can be matched, including types produced by macros, like
+//! `match my_type!()`.
+//!
 //! ## Return types
 //!
 //! Values can be returned from the matches, but have to be explicitly
@@ -83,6 +103,134 @@
 //! assert_eq!(result, "x is a 32-bit integer!");
 //! ```
 //!
+//! An arm can also return an `impl Trait` type:
+//!
+//! ```
+//! # use spez::spez;
+//! let iter = spez! {
+//!     for x = 4;
+//!     match i32 -> impl Iterator<Item = i32> { 0..x }
+//!     match &str -> i32 { 0 }
+//! };
+//! assert_eq!(iter.sum::<i32>(), 6);
+//! ```
+//!
+//! Arms that never return (e.g. because they always panic) can use `-> !`.
+//!
+//! When most arms return the same type, it can be given once after the input
+//! instead. It is then used for all arms that don't specify their own return
+//! type.
+//!
+//! ```
+//! # use spez::spez;
+//! let x = 0;
+//! let result = spez! {
+//!     for x -> i32;
+//!     match i32 { 1 }
+//!     match &str { 2 }
+//!     match u8 -> i32 { 3 }
+//! };
+//! assert_eq!(result, 1);
+//! ```
+//!
+//! (The return type can not be inferred from the bodies, as every body ends up
+//! in a separate trait implementation, which needs to spell out its types. A
+//! single associated type shared by all arms would still have to be set to a
+//! written type in each of these implementations.)
+//!
+//! An input that is valid on its own, like `f as fn() -> i32`, is never split
+//! up this way. Put such an input in parentheses to give a return type after it.
+//! Otherwise, the input ends at the first `->` that leaves a valid input and
+//! type on both sides, so the return type can contain `->` itself, like
+//! `for g = f -> Option<fn() -> i32>;`.
+//!
+//! Instead of a block, the body can also be given as `=>` followed by a single
+//! expression.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = 5;
+//!     match i32 -> i32 => x + 1
+//!     match &str -> i32 { 0 }
+//! };
+//! assert_eq!(result, 6);
+//! ```
+//!
+//! In the return type, `Self` stands for the type matched by the arm. A body of
+//! just `self` returns the input unchanged.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for 5u16;
+//!     match u8 | u16 -> Self => self
+//!     else -> u16 { 0 }
+//! };
+//! assert_eq!(result, 5);
+//! ```
+//!
+//! A `return` in the body of an arm returns from that arm, so the returned
+//! value becomes the value of the `spez! {}` expression. It does not return
+//! from the function around the `spez! {}`. The same holds for the `?`
+//! operator.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = 5;
+//!     match i32 -> &'static str {
+//!         if x > 3 {
+//!             return "big";
+//!         }
+//!         "small"
+//!     }
+//!     match &str -> &'static str { "str" }
+//! };
+//! assert_eq!(result, "big");
+//! ```
+//!
+//! To propagate an error with `?` to the function around the `spez! {}`, return
+//! a `Result` from the arms, and apply the `?` to the result of the `spez! {}`:
+//!
+//! ```
+//! # use spez::spez;
+//! use std::num::ParseIntError;
+//! fn parse(s: &str) -> Result<i32, ParseIntError> {
+//!     let n = spez! {
+//!         for s -> Result<i32, ParseIntError>;
+//!         match &str {
+//!             let n: i32 = s.parse()?; // Returns the error from the arm.
+//!             Ok(n * 2)
+//!         }
+//!         else { Ok(0) }
+//!     }?; // Returns the error from `parse`.
+//!     Ok(n + 1)
+//! }
+//! assert_eq!(parse("2"), Ok(5));
+//! assert!(parse("a").is_err());
+//! ```
+//!
+//! ## Multiple types
+//!
+//! Several types can share the same body and return type by separating them
+//! with `|`.
+//!
+//! ```
+//! # use spez::spez;
+//! let x = 5u32;
+//! let result = spez! {
+//!     for x;
+//!     match i32 | i64 | u32 -> &'static str {
+//!         "integer"
+//!     }
+//!     match f32 | f64 -> &'static str {
+//!         "float"
+//!     }
+//! };
+//! assert_eq!(result, "integer");
+//! ```
+//!
 //! ## Generic matches
 //!
 //! Generic matches are also possible. Generic variables can be defined
@@ -109,253 +257,2649 @@
 //! assert_eq!(result, 1);
 //! ```
 //!
-//! # Consuming the input
+//! A set of bounds that is used often can be given a name with a trait that
+//! has them as supertraits and is implemented for every type that has them,
+//! like `trait Common: Debug + Clone {}` with `impl<T: Debug + Clone> Common
+//! for T {}`. A type that misses any of the supertraits does not match
+//! `match<T: Common> T`.
 //!
-//! The input (after the `for`) is consumed and made available to the `match`
-//! bodies.
+//! As a shorthand, `impl Bounds` can be used in the type, like in the type of
+//! a function argument. `match impl Debug` is the same as `match<T: Debug> T`,
+//! and `match Vec<impl Clone>` as `match<T: Clone> Vec<T>`. This can not be
+//! used in an arm with multiple types.
+//!
+//! ```
+//! # use spez::spez;
+//! use std::fmt::Debug;
+//! let result = spez! {
+//!     for x = 123;
+//!     match impl Debug -> String { format!("{:?}", x) }
+//!     else -> String { String::from("?") }
+//! };
+//! assert_eq!(result, "123");
+//! ```
 //!
-//! (If you don't want to consume the input, take a reference and also prepend
-//! a `&` to the types you're matching.)
+//! The input is a value, so it is always `Sized`. Unsized types like `str` or
+//! `[T]` can only be matched behind a reference, like `match<T: ?Sized> &T` or
+//! `match str` with `for &name;`. In `match<T: ?Sized> T`, the `?Sized` is
+//! ignored.
 //!
 //! ```
 //! # use spez::spez;
-//! # use core::ops::Deref;
-//! let x = Box::new(123);
+//! macro_rules! sized {
+//!     ($e:expr) => {
+//!         spez! {
+//!             for x = $e;
+//!             match<T> &T -> bool { true }
+//!             match<T: ?Sized> &T -> bool { false }
+//!         }
+//!     };
+//! }
+//! assert!(sized!(&5));
+//! assert!(!sized!("abc"));
+//! assert!(!sized!(&[1, 2][..]));
+//! ```
+//!
+//! The generic parameters are in scope in the body, so they can be used in
+//! types and turbofishes, like `std::any::type_name::<T>()`. Lifetime and
+//! const generic parameters can be used as well:
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for [1u8, 2, 3, 4];
+//!     match<const N: usize> [u8; N] -> usize { N }
+//!     match _ -> usize { 0 }
+//! };
+//! assert_eq!(result, 4);
+//!
+//! let s = String::from("abc");
+//! let result = spez! {
+//!     for x = s.as_str();
+//!     match<'a> &'a str -> &'a str { x }
+//!     match _ -> &'static str { "" }
+//! };
+//! assert_eq!(result, "abc");
+//! ```
+//!
+//! As in the header of an `impl`, the lifetime of a type like `Cow<'_, str>`
+//! can't be left out entirely, but it can be written as `'_`.
+//!
+//! Just like for a function, a lifetime that is left out in the return type is
+//! that of the matched type, if it has exactly one. So this works too:
+//!
+//! ```
+//! # use spez::spez;
+//! let s = String::from("abc");
+//! let result = spez! {
+//!     for &s;
+//!     match String -> &str { s.as_str() }
+//!     else -> &str { "" }
+//! };
+//! assert_eq!(result, "abc");
+//! ```
+//!
+//! A predicate in the `where` clause can also have no bounds, like the
+//! `[(); N - 1]:` that the unstable `generic_const_exprs` feature uses. Note
+//! that such a predicate doesn't make an arm skip the types for which the
+//! expression can't be evaluated: that is an error instead.
+//!
+//! As this is a procedural macro crate, it can not provide traits to use as a
+//! bound. To match a family of types, like all built-in integer types, define
+//! a trait for them yourself:
+//!
+//! ```
+//! # use spez::spez;
+//! trait Integer {}
+//! macro_rules! impl_integer {
+//!     ($($t:ty)*) => { $(impl Integer for $t {})* };
+//! }
+//! impl_integer!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize);
+//!
+//! macro_rules! is_integer {
+//!     ($e:expr) => {
+//!         spez! {
+//!             for $e;
+//!             match impl Integer -> bool { true }
+//!             else -> bool { false }
+//!         }
+//!     };
+//! }
+//! assert!(is_integer!(1u8));
+//! assert!(is_integer!(-1i64));
+//! assert!(!is_integer!(1.0f64));
+//! ```
+//!
+//! ## Priority
+//!
+//! When the arms are generated in an arbitrary order, for example by another
+//! macro, the order they are tried in can be given as `match(priority = N)`.
+//! Arms with a lower number are tried first, and arms without a priority are
+//! tried after all arms with one. Arms with the same priority are tried in the
+//! order they are written in. An arm with a `!` bound takes the arm that
+//! follows it along to its priority. The index given by
+//! [`spez_with_index!`](#which-arm-was-used) is still the position of the arm
+//! as written.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = 123i32;
+//!     match(priority = 2) <T: std::fmt::Debug> T -> i32 { 1 }
+//!     match(priority = 1) i32 -> i32 { 2 }
+//! };
+//! assert_eq!(result, 2);
+//! ```
+//!
+//! ## Fallback
+//!
+//! A `match _` arm matches any type. It is a shorthand for `match<T> T`, for
+//! when the body doesn't need to name the type. Just like any other arm, it
+//! only applies if none of the arms before it match.
+//!
+//! An `else` arm is the same as `match _`, but can only be used as the last arm.
+//!
+//! ```
+//! # use spez::spez;
+//! let x = 1.5f32;
 //! let result = spez! {
 //!     for x;
-//!     match<T: Deref<Target = i32>> T -> i32 {
-//!         *x
+//!     match i32 -> &'static str {
+//!         "integer"
 //!     }
-//!     match i32 -> i32 {
-//!         x
+//!     match _ -> &'static str {
+//!         "something else"
 //!     }
 //! };
-//! assert_eq!(result, 123);
+//! assert_eq!(result, "something else");
+//!
+//! let result = spez! {
+//!     for x;
+//!     match f32 -> &'static str {
+//!         "float"
+//!     }
+//!     else -> &'static str {
+//!         "something else"
+//!     }
+//! };
+//! assert_eq!(result, "float");
 //! ```
 //!
-//! # Expressions as input
+//! `default` can be written instead of `else`. To make sure that there is
+//! always an arm to fall back to, for example in a macro that passes arms on
+//! to `spez!`, add `#[spez(require_default)]` before the `for`. It is then
+//! an error if the last arm is not an `else` or `default` arm.
 //!
-//! Not just variable names, but full expressions can be given as input.
-//! However, if you want to refer to them from the match bodies, you need to
-//! prepend `name =` to give the input a name.
+//! ```compile_fail
+//! # use spez::spez;
+//! let result = spez! {
+//!     #[spez(require_default)]
+//!     for x = 1;
+//!     match i32 -> i32 { 0 }
+//! }; // ERROR
+//! ```
 //!
 //! ```
 //! # use spez::spez;
 //! let result = spez! {
-//!     for 1 + 1;
-//!     match i32 -> i32 { 0 }
-//!     match i64 -> i32 { 1 }
+//!     #[spez(require_default)]
+//!     for x = 1i32;
+//!     match u8 -> i32 { 0 }
+//!     default -> i32 { 1 }
+//! };
+//! assert_eq!(result, 1);
+//! ```
+//!
+//! Instead of an `else` arm, the last arm can be followed by just an
+//! expression. This is the same as `else => <expression>`, so it gets the
+//! shared return type, if there is one.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = "abc" -> i32;
+//!     match i32 { x }
+//!     0
 //! };
 //! assert_eq!(result, 0);
 //! ```
 //!
+//! With `#[spez(panic_default)]`, a `default` arm is added that panics at run
+//! time, with a message naming the type of the input, in place of the error at
+//! compile time. That is useful in macros whose expansions don't all run, so
+//! a missing arm shouldn't fail the build. Without a shared return type, this
+//! arm returns `!`, so in `async` mode or with `spez_fn!`, give one.
+//!
+//! ```should_panic
+//! # use spez::spez;
+//! let result = spez! {
+//!     #[spez(panic_default)]
+//!     for x = "abc" -> i32;
+//!     match i32 { x }
+//! }; // Panics: no arm of spez! matches the type `&str`
 //! ```
+//!
+//! To check that a fixed set of types is handled by arms other than the
+//! fallback, list them in `#[spez(exhaustive(..))]`. It is then an error if
+//! one of them is only matched by the `else` or `default` arm. Arms with a
+//! guard don't count, as the guard could fall through. Just like the error for
+//! when no arm matches, this error shows up in `cargo build`, but not in
+//! `cargo check`.
+//!
+//! ```compile_fail
 //! # use spez::spez;
+//! struct A;
+//! struct B;
 //! let result = spez! {
-//!     for x = 1 + 1;
-//!     match i32 -> i32 { x }
-//!     match i64 -> i32 { 1 }
+//!     #[spez(exhaustive(A, B))] // ERROR: `B` is not matched by any arm
+//!     for x = A;
+//!     match A -> i32 { 0 }
+//!     else -> i32 { 1 }
 //! };
-//! assert_eq!(result, 2);
 //! ```
 //!
-//! # Capturing variables
+//! Arms after an arm that matches any type (like `match _` or `match<T> T`
+//! without bounds) can never be selected. These result in a warning, which
+//! can be silenced with `#[allow(deprecated)]` on the arm.
 //!
-//! Unfortunately, you can't refer to variables of the scope around the `spez! {}` macro:
+//! For the same reason, two arms matching the same concrete type (without a
+//! guard) are an error:
 //!
 //! ```compile_fail
-//! let a = 1;
+//! # use spez::spez;
 //! let result = spez! {
 //!     for x = 1;
-//!     match i32 {
-//!         println!("{}", a); // ERROR
-//!     }
+//!     match i32 -> i32 { 0 }
+//!     match i32 -> i32 { 1 } // ERROR
 //! };
 //! ```
 //!
-//! # In a generic function
-//!
-//! As mentioned above, the macro is of not much use in generic context, as the
-//! specialization is resolved based on the bounds rather than on the actual
-//! type in the instantiation of the generic function:
+//! Concrete types can have a `where` clause as well. Such an arm only matches
+//! if the bounds hold.
 //!
 //! ```
 //! # use spez::spez;
-//! # use std::fmt::Debug;
-//! fn f<T: Debug>(v: T) -> &'static str {
-//!     spez! {
-//!         for v;
-//!         match i32 -> &'static str {
-//!             ":)"
-//!         }
-//!         match<T: Debug> T -> &'static str {
-//!             ":("
-//!         }
-//!         match<T> T -> &'static str {
-//!             ":(("
-//!         }
-//!     }
-//! }
-//! assert_eq!(f(0i32), ":(");
+//! trait Special {}
+//! impl Special for i64 {}
+//! let result = spez! {
+//!     for x = 2i32;
+//!     match i32 where i32: Special -> i32 { 0 }
+//!     match i32 -> i32 { x }
+//! };
+//! assert_eq!(result, 2);
 //! ```
 //!
-//! # In a macro
-//!
-//! This is a demonstration of a macro that prints the `Debug` output of a
-//! value, but falls back to `"<object of type ...>"` if it doesn't implement
-//! `Debug`.
+//! To match the types that do *not* satisfy some bound, write `where !Bound`
+//! after a `match<T> T`. Such an arm must be followed by an arm that matches
+//! any type, which then gets used for the types that do satisfy the bound.
 //!
 //! ```
 //! # use spez::spez;
 //! # use std::fmt::Debug;
-//! macro_rules! debug {
-//!     ($e:expr) => {
-//!         spez! {
-//!             for x = $e;
-//!             match<T: Debug> T {
-//!                 println!("{:?}", x);
-//!             }
-//!             match<T> T {
-//!                 println!("<object of type {}>", std::any::type_name::<T>());
-//!             }
-//!         }
-//!     }
-//! }
-//! debug!(123);
 //! # struct NoDebugType;
-//! debug!(NoDebugType);
+//! let result = spez! {
+//!     for x = NoDebugType;
+//!     match<T> T where !Debug -> &'static str { "no Debug" }
+//!     else -> &'static str { "Debug" }
+//! };
+//! assert_eq!(result, "no Debug");
 //! ```
-//! 
-//! # Running a mutable trait method
-//! 
-//! It is also possible to conditionally run a mutable trait method for an
-//! object, depending on whether or not that object implements a certain trait
-//! 
+//!
+//! If no arm matches, compilation fails with the error "no arm of spez!
+//! matches the type of the input". This error is reported after type checking,
+//! so it shows up in `cargo build`, but not in `cargo check`.
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = 1u32;
+//!     match u8 -> i32 { 0 }
+//!     match i64 -> i32 { 1 }
+//! };
+//! ```
+//!
+//! ## Guards
+//!
+//! An arm can have an `if` guard after its type (and `where` clause). The guard
+//! is evaluated at run time, after the type has matched. If it evaluates to
+//! `false`, the remaining arms are tried instead, as if the guarded arm didn't
+//! exist. A guard can not be used on the last arm.
+//!
 //! ```
 //! # use spez::spez;
-//! #
-//! struct MyStruct1(u32);
-//! struct MyStruct2(u32);
-//! 
-//! trait Increment {
-//! 	fn inc(&mut self);
-//! }
-//! 
-//! impl Increment for &mut MyStruct1 {
-//! 	fn inc(&mut self) {
-//! 		self.0 += 1;
-//! 	}
-//! }
-//! 
-//! let mut my_object1 = MyStruct1(0);
-//! let mut my_object2 = MyStruct2(0);
-//! 
-//! assert_eq!(my_object1.0, 0);
-//! assert_eq!(my_object2.0, 0);
-//! 
-//! spez! {
-//!		for x = &mut my_object1;
-//!		match<T> T where T: Increment {
-//!			x.inc();
-//!		}
-//! 	match<T> T {}
-//!	};
-//! spez! {
-//!		for x = &mut my_object2;
-//!		match<T> T where T: Increment {
-//!			x.inc();
-//!		}
-//! 	match<T> T {}
-//!	};
-//! 
-//! assert_eq!(my_object1.0, 1);
-//! assert_eq!(my_object2.0, 0);
+//! let f = |v: i32| spez! {
+//!     for x = v;
+//!     match i32 if x > 100 -> &'static str { "big" }
+//!     match i64 -> &'static str { "i64" }
+//!     match i32 -> &'static str { "small" }
+//! };
+//! assert_eq!(f(1000), "big");
+//! assert_eq!(f(1), "small");
 //! ```
+//!
+//! The remaining arms are selected in the context of the guarded arm. For a
+//! generic arm, this means only arms that apply to all types matching that
+//! arm can be selected, just like [in a generic
+//! function](#in-a-generic-function). The remaining arms must also have the
+//! same return type as the guarded arm. If they don't, the error points at the
+//! guard.
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! let f = |v: i32| spez! {
+//!     for x = v -> i32;
+//!     match i32 if x > 100 -> &'static str { "big" } // ERROR
+//!     match i32 { x }
+//! };
+//! ```
+//!
+//! Without guards, the arm is selected entirely at compile time, and the
+//! expansion does not branch at run time. To make sure of that, add
+//! `#[spez(static_only)]` before the `for`. Any guard is then an error.
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! let f = |v: i32| spez! {
+//!     #[spez(static_only)]
+//!     for x = v;
+//!     match i32 if x > 100 -> &'static str { "big" } // ERROR
+//!     match i32 -> &'static str { "small" }
+//! };
+//! ```
+//!
+//! ## Unsafe arms
+//!
+//! The bodies of arms can contain `unsafe` blocks. As a shorthand, an arm can
+//! be written as `unsafe match` (or `unsafe else`), which puts its whole body
+//! in an `unsafe` block. The guard of the arm is not included.
+//!
+//! ```
+//! # use spez::spez;
+//! let bytes: &[u8] = b"abc";
+//! let result = spez! {
+//!     for x = bytes;
+//!     // SAFETY: `bytes` is valid UTF-8.
+//!     unsafe match<'a> &'a [u8] -> &'a str { std::str::from_utf8_unchecked(x) }
+//!     else -> &'static str { "" }
+//! };
+//! assert_eq!(result, "abc");
+//! ```
+//!
+//! ## Attributes
+//!
+//! Attributes like `#[cfg]` and `#[allow]` can be put on an arm, and apply to
+//! the code generated for that arm. `#[inline]`, `#[cold]` and
+//! `#[track_caller]` apply to the function that runs the body. Inner
+//! attributes at the start of the body of an arm (e.g.
+//! `#![allow(unused_variables)]`) only apply to that body.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for 0u8;
+//!     #[cfg(unix)]
+//!     match u8 -> &'static str { "unix" }
+//!     #[cfg(not(unix))]
+//!     match u8 -> &'static str { "not unix" }
+//! };
+//! assert_eq!(result, if cfg!(unix) { "unix" } else { "not unix" });
+//! ```
+//!
+//! As not every arm uses the input, no warning is given for an arm that doesn't
+//! use it. An arm can ask for that warning with `#[warn(unused_variables)]`
+//! (or `deny`, or the `unused` group), on the arm or in its body.
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = 0u8; // ERROR: unused variable: `x`
+//!     #[deny(unused_variables)]
+//!     match u8 -> i32 { 1 }
+//!     else -> i32 { 0 }
+//! };
+//! ```
+//!
+//! ## Panics
+//!
+//! A panic in the body of an arm reports the location of that code, as usual.
+//! With `#[spez(track_caller)]`, the bodies instead get the location of the
+//! `spez!` itself from `Location::caller()`, and so do the panics in them. In
+//! a function with `#[track_caller]`, that is the location of its caller.
+//!
+//! ```should_panic
+//! # use spez::spez;
+//! #[track_caller]
+//! fn expect_u8<T>(x: T) -> u8 {
+//!     spez! {
+//!         #[spez(track_caller)]
+//!         for x;
+//!         match u8 -> u8 { x }
+//!         else -> u8 { panic!("expected a `u8`") }
+//!     }
+//! }
+//!
+//! expect_u8(1u16); // The panic is reported here.
+//! ```
+//!
+//! This can not be used with `async` or `spez_fn!`.
+//!
+//! ## Arms from a trait
+//!
+//! An arm can also be written as `use trait Trait -> Type;`. It matches any
+//! type that implements `Trait`, and runs its `spez` method on the input. This
+//! way, the types that get this arm can be chosen elsewhere, by implementing
+//! the trait, even in another module or crate. Like any other arm, it is only
+//! selected if no earlier arm matches.
+//!
+//! ```
+//! # use spez::spez;
+//! mod describe {
+//!     pub trait Describe {
+//!         fn spez(self) -> String;
+//!     }
+//!
+//!     impl Describe for std::time::Duration {
+//!         fn spez(self) -> String {
+//!             format!("{} ms", self.as_millis())
+//!         }
+//!     }
+//! }
+//!
+//! let result = spez! {
+//!     for x = std::time::Duration::from_millis(5);
+//!     match u64 -> String { format!("{x}") }
+//!     use trait describe::Describe -> String;
+//!     else -> String { String::new() }
+//! };
+//! assert_eq!(result, "5 ms");
+//! ```
+//!
+//! The method gets the input the same way as the body of any other arm, so
+//! when the input is borrowed (like with `for &name;`), it takes `&self` (or
+//! `&mut self`) instead.
+//!
+//! ## Number of arms
+//!
+//! Each arm adds one step of auto-deref to the selection of the arm. The
+//! number of steps is limited by the recursion limit, which is 128 by default.
+//! To use more arms than that, raise it with `#![recursion_limit = "256"]` at
+//! the root of your crate.
+//!
+//! As every arm also adds to the time it takes to compile a `spez!`, the
+//! number of arms can be capped by setting the `SPEZ_MAX_ARMS` environment
+//! variable when compiling, e.g. in the `[env]` section of
+//! `.cargo/config.toml`. Any `spez!` with more arms than that is an error.
+//! Only the arms that are written out count, not those that macros like
+//! `spez_matches!` or `#[spez(panic_default)]` add themselves. The macro
+//! defined by [`#[derive(Spez)]`](#dispatching-to-an-enum) is the exception:
+//! it has an arm for each variant and an `else` arm, and these all count.
+//! Since Cargo does not know that the macros read this variable, code that
+//! was already compiled is only checked again after it changes.
+//!
+//! ## Syntax errors
+//!
+//! The errors for the most common mistakes point at where the syntax goes
+//! wrong. A missing `;` after the input is reported at the first arm, as
+//! "expected `;` before the first arm":
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! let result = spez! {
+//!     for 1i32
+//!     match i32 -> i32 { 0 }
+//! };
+//! ```
+//!
+//! A `match` without a type gives "expected a type after `match`":
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! let result = spez! {
+//!     for 1i32;
+//!     match -> i32 { 0 }
+//! };
+//! ```
+//!
+//! Anything else than `->` before the return type gives "expected `{`, `=>`,
+//! or `->` followed by the return type":
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! let result = spez! {
+//!     for 1i32;
+//!     match i32 - i32 { 0 }
+//! };
+//! ```
+//!
+//! An arm without a body gives "expected the body of the arm, as `{ .. }` or
+//! `=> expression`":
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! let result = spez! {
+//!     for 1i32;
+//!     match i32 -> i32
+//! };
+//! ```
+//!
+//! And a `spez!` without any arms gives "spez! requires at least one match
+//! arm":
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! spez! {
+//!     for 1i32;
+//! }
+//! ```
+//!
+//! # Consuming the input
+//!
+//! The input (after the `for`) is consumed and made available to the `match`
+//! bodies.
+//!
+//! ```
+//! # use spez::spez;
+//! # use core::ops::Deref;
+//! let x = Box::new(123);
+//! let result = spez! {
+//!     for x;
+//!     match<T: Deref<Target = i32>> T -> i32 {
+//!         *x
+//!     }
+//!     match i32 -> i32 {
+//!         x
+//!     }
+//! };
+//! assert_eq!(result, 123);
+//! ```
+//!
+//! If the body of the selected arm doesn't move the input elsewhere, the input
+//! is dropped right after the body has run, before the `spez! {}` expression
+//! returns its value. (In `async` mode, it is dropped when the future
+//! completes.)
+//!
+//! If you don't want to consume the input, write `for &name;` (or `for &mut
+//! name;`) instead. The input is then borrowed, and the `&` (or `&mut`) is
+//! added to the types of all arms, so `match String` matches a borrowed
+//! `String`. In the bodies, `name` refers to the reference.
+//!
+//! ```
+//! # use spez::spez;
+//! let s = String::from("abc");
+//! let len = spez! {
+//!     for &s;
+//!     match String -> usize { s.len() }
+//!     else -> usize { 0 }
+//! };
+//! assert_eq!(len, 3);
+//! assert_eq!(s, "abc"); // Not consumed.
+//! ```
+//!
+//! With `for &mut name;`, an arm that only needs a shared reference can be
+//! written as `match &Type`. It matches the same types as `match Type`, but
+//! `name` is a `&Type` in its body (and its guard still gets the `&mut`). To
+//! match a mutable reference to a shared reference, use `match (&Type)`.
+//!
+//! ```
+//! # use spez::spez;
+//! let mut v = vec![1i32];
+//! let len = spez! {
+//!     for &mut v;
+//!     match Vec<i32> -> usize { v.push(2); v.len() }
+//!     match<T> &T -> usize { 0 }
+//! };
+//! assert_eq!(len, 2);
+//! assert_eq!(v, [1, 2]);
+//! ```
+//!
+//! For an input that is not just a name, write `for ref name = <expression>;`
+//! (or `ref mut`). This borrows the value of the expression in the same way.
+//!
+//! ```
+//! # use spez::spez;
+//! let v = vec![(1, 'a'), (2, 'b')];
+//! let first = spez! {
+//!     for ref x = v[0];
+//!     match (i32, char) -> i32 { x.0 }
+//!     else -> i32 { 0 }
+//! };
+//! assert_eq!(first, 1);
+//! assert_eq!(v.len(), 2); // Not consumed.
+//! ```
+//!
+//! `spez_ref!` works like `spez!`, but always borrows the input this way:
+//!
+//! ```
+//! # use spez::spez_ref;
+//! let s = String::from("abc");
+//! let len = spez_ref! {
+//!     for x = s;
+//!     match String -> usize { x.len() }
+//!     else -> usize { 0 }
+//! };
+//! assert_eq!(len, 3);
+//! assert_eq!(s, "abc"); // Not consumed.
+//! ```
+//!
+//! With a tuple pattern, like `for (a, b) = pair;`, the names refer to
+//! references to the elements. A tuple of variables, like `for (a, b);`, can
+//! not be borrowed this way, as building the tuple would move them.
+//!
+//! To match the types of references yourself, give the reference a name
+//! instead: `for r = &s;` followed by arms like `match &String`. Each arm
+//! decides on its own which kind of reference (if any) it takes, so arms like
+//! `match &mut Type`, `match &Type` and `match Type` can be mixed.
+//!
+//! For example, to tell an owned `String` from a string slice:
+//!
+//! ```
+//! # use spez::spez;
+//! macro_rules! kind {
+//!     ($e:expr) => {
+//!         spez! {
+//!             for s = $e;
+//!             match String -> &'static str { "String" }
+//!             match &str | &String -> &'static str { "borrowed" }
+//!             else -> &'static str { "something else" }
+//!         }
+//!     };
+//! }
+//! let s = String::from("abc");
+//! assert_eq!(kind!(s.clone()), "String");
+//! assert_eq!(kind!("abc"), "borrowed");
+//! assert_eq!(kind!(&s), "borrowed");
+//! ```
+//!
+//! No deref coercion happens when selecting an arm, so a `&String` does not
+//! match `&str` on its own. List both, as above, or use a bound like
+//! `match<S: AsRef<str>> S` to accept anything that can be viewed as a `&str`.
+//!
+//! ## Nesting
+//!
+//! A `spez!` can be used in the body of an arm of another one, to specialize
+//! the same value further. As the outer arm owns the input, the inner one
+//! should borrow it with `for &name;`, so it can still be used afterwards. If
+//! the outer input is already borrowed, `for name;` with arms like `match
+//! &Type` passes the reference on as it is.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = String::from("abc");
+//!     match String -> usize {
+//!         let inner = spez! {
+//!             for &x;
+//!             match String -> usize { x.len() }
+//!             else -> usize { 0 }
+//!         };
+//!         inner + x.len()
+//!     }
+//! };
+//! assert_eq!(result, 6);
+//! ```
+//!
+//! In an arm with a generic type, the inner `spez!` only knows the bounds of
+//! the arm, just like [in a generic function](#in-a-generic-function).
+//!
+//! # Expressions as input
+//!
+//! Not just variable names, but full expressions can be given as input.
+//! However, if you want to refer to them from the match bodies, you need to
+//! prepend `name =` to give the input a name.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for 1 + 1;
+//!     match i32 -> i32 { 0 }
+//!     match i64 -> i32 { 1 }
+//! };
+//! assert_eq!(result, 0);
+//! ```
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = 1 + 1;
+//!     match i32 -> i32 { x }
+//!     match i64 -> i32 { 1 }
+//! };
+//! assert_eq!(result, 2);
+//! ```
+//!
+//! The name is bound mutably in every arm, so the bodies can modify the
+//! input. Writing `mut` before the name is an error, as it would not change
+//! anything.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for v = vec![1];
+//!     match Vec<i32> -> Vec<i32> {
+//!         v.push(2);
+//!         v
+//!     }
+//!     else -> Vec<i32> { Vec::new() }
+//! };
+//! assert_eq!(result, [1, 2]);
+//! ```
+//!
+//! This includes block expressions, which can be used for any setup that is
+//! needed to compute the input:
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = {
+//!         let v = vec![3, 1, 2];
+//!         v.into_iter().max().unwrap()
+//!     };
+//!     match i32 -> i32 { x }
+//!     match i64 -> i32 { 1 }
+//! };
+//! assert_eq!(result, 3);
+//! ```
+//!
+//! ## Multiple inputs
+//!
+//! To specialize on the types of multiple values at once, give a tuple as the
+//! input and match on tuple types. A tuple of names (or a tuple pattern before
+//! the `=`) makes the elements available separately to the bodies of arms
+//! matching a tuple type.
+//!
+//! ```
+//! # use spez::spez;
+//! let a = 1;
+//! let b = "two";
+//! let result = spez! {
+//!     for (a, b);
+//!     match (i32, i32) -> i32 { a + b }
+//!     match (i32, &str) -> i32 { a + b.len() as i32 }
+//! };
+//! assert_eq!(result, 4);
+//!
+//! let result = spez! {
+//!     for (a, (b, c)) = (1, (2, 3));
+//!     match (i32, (i32, i32)) -> i32 { a + b + c }
+//!     else -> i32 { 0 }
+//! };
+//! assert_eq!(result, 6);
+//! ```
+//!
+//! Note that the tuple is matched as a whole. Auto(de)ref only applies to the
+//! tuple itself, never to its elements (or to elements of nested tuples), so a
+//! `(&i32, i32)` input does not match `(i32, i32)`. Use a generic arm like
+//! `match<A: Trait, B> (A, B)` to specialize on a single element.
+//!
+//! To instead run the same arms on several values one by one, write `for name
+//! in [a, b, c];`. An arm is selected for each of the values on its own, and
+//! the result is an array with the value of the selected arm for each of them.
+//! All arms must then have the same return type, which can be given once as
+//! `for name in [..] -> Type;`.
+//!
+//! ```
+//! # use spez::spez;
+//! let s = String::from("abc");
+//! let result = spez! {
+//!     for x in [5u8, "abc", s] -> usize;
+//!     match u8 { x as usize }
+//!     match &str { x.len() }
+//!     else { 0 }
+//! };
+//! assert_eq!(result, [5, 3, 0]);
+//! ```
+//!
+//! This can not be used with `async` or `spez_fn!`. The variables of
+//! `capture(..)` are moved for each of the values, so they must be `Copy`.
+//! To share anything else, capture a reference to it.
+//!
+//! ## Naming the input per arm
+//!
+//! A name can also be given to the input in a single arm, by adding `as name`
+//! after the type. Within that arm, this is used instead of the name given
+//! after `for`.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for 1 + 1;
+//!     match i32 as n -> i32 { n + 1 }
+//!     match i64 as m -> i32 { 1 }
+//! };
+//! assert_eq!(result, 3);
+//! ```
+//!
+//! Instead of a name, this can also be a pattern that destructures the
+//! input. The pattern must match any value of the type, so it can't contain
+//! literals or ranges.
+//!
+//! ```
+//! # use spez::spez;
+//! struct Point(i32, i32);
+//! struct Size {
+//!     w: u32,
+//!     h: u32,
+//! }
+//!
+//! let result = spez! {
+//!     for Point(1, 2);
+//!     match Point as Point(x, y) -> i32 { x + y }
+//!     match Size as Size { w, h } -> i32 { (w * h) as i32 }
+//! };
+//! assert_eq!(result, 3);
+//! ```
+//!
+//! # Async
+//!
+//! Writing `async` before the `for` makes the bodies of all arms `async`, so
+//! they can `.await`. The `spez! {}` macro then evaluates to a future, which
+//! resolves to the result of the selected arm.
+//!
+//! ```
+//! # use spez::spez;
+//! async fn double(x: i32) -> i32 {
+//!     x * 2
+//! }
+//!
+//! async fn f() -> i32 {
+//!     spez! {
+//!         async for x = 21;
+//!         match i32 -> i32 { double(x).await }
+//!         else -> i32 { 0 }
+//!     }
+//!     .await
+//! }
+//! # let _ = f();
+//! ```
+//!
+//! The arm is selected, and the input is consumed, when the `spez! {}`
+//! expression is evaluated. The body of the arm (including its guard) only
+//! runs when the future is polled. Arms can not return `!` in `async` mode.
+//!
+//! # Deferred evaluation
+//!
+//! `spez_fn!` works like `spez!`, but evaluates to a closure that runs the
+//! selected arm when it is called, in place of the value of that arm. The
+//! closure is an `impl FnOnce() -> R`, where `R` is the return type of the
+//! selected arm.
+//!
+//! ```
+//! # use spez::spez_fn;
+//! let f = spez_fn! {
+//!     for x = 21i32;
+//!     match i32 -> i32 { x * 2 }
+//!     else -> i32 { 0 }
+//! };
+//! assert_eq!(f(), 42);
+//! ```
+//!
+//! Just like in `async` mode, the arm is selected and the input is consumed
+//! right away, but the body of the arm (including its guard) only runs when
+//! the closure is called. Arms can not return `!` or `impl Trait`.
+//!
+//! With `async`, the closure returns the future instead, so the result is an
+//! `impl FnOnce() -> impl Future<Output = R>`. The body runs when that future
+//! is polled, and can `.await` like in any `async` spez!.
+//!
+//! ```
+//! # use spez::spez_fn;
+//! async fn double(x: i32) -> i32 {
+//!     x * 2
+//! }
+//!
+//! async fn f() -> i32 {
+//!     let f = spez_fn! {
+//!         async for x = 21i32;
+//!         match i32 -> i32 { double(x).await }
+//!         else -> i32 { 0 }
+//!     };
+//!     f().await
+//! }
+//! # drop(f());
+//! ```
+//!
+//! # Dispatching to an enum
+//!
+//! For an enum with only variants like `Name(Type)`, `#[derive(Spez)]`
+//! defines a macro that takes an expression, and evaluates to `Some` with the
+//! value of that expression in the variant that holds its type, or to `None`
+//! if there is no such variant. The macro is named after the enum in snake
+//! case (`Payload` gets `payload!`), or as given by `#[spez(name = "..")]`.
+//!
+//! ```
+//! use spez::Spez;
+//!
+//! #[derive(Debug, PartialEq, Spez)]
+//! enum Payload {
+//!     Number(i32),
+//!     Text(String),
+//!     Bytes(Vec<u8>),
+//! }
+//!
+//! assert_eq!(payload!(5), Some(Payload::Number(5)));
+//! assert_eq!(payload!(String::from("a")), Some(Payload::Text(String::from("a"))));
+//! assert_eq!(payload!(1.5), None);
+//! ```
+//!
+//! This is a `macro_rules!` macro, so it can only be used after the enum, and
+//! the name of the enum must be in scope where it is used. The enum can not
+//! be generic, and no two variants can hold the same type.
+//!
+//! The macro refers to `spez!` as `::spez::spez!`. If `spez` is only
+//! available under another path, for example through a re-export from another
+//! crate, give that path with `#[spez(crate = path::to::spez)]`.
+//!
+//! # Checking for a trait
+//!
+//! To only check whether the type of an expression satisfies some bounds, use
+//! `spez_matches!`. It evaluates to a `bool`, and borrows the expression
+//! rather than consuming it.
+//!
+//! ```
+//! # use spez::spez_matches;
+//! use std::fmt::Debug;
+//! let s = String::from("abc");
+//! assert!(spez_matches!(s, Debug + Clone));
+//! assert!(!spez_matches!(s, Copy));
+//! assert_eq!(s, "abc");
+//! ```
+//!
+//! To check several bounds at once, use `spez_all_matches!`. It takes a list
+//! of bounds, and evaluates to an array with a `bool` for each of them. Unlike
+//! the arms of `spez!`, where the first matching arm wins, every bound is
+//! checked on its own.
+//!
+//! ```
+//! # use spez::spez_all_matches;
+//! use std::fmt::{Debug, Display};
+//! let s = String::from("abc");
+//! let matches = spez_all_matches!(s, [Debug, Display, Copy, Clone + Default]);
+//! assert_eq!(matches, [true, true, false, true]);
+//! ```
+//!
+//! `spez_assert_impl!` takes the same input, but instead fails to compile if
+//! the bounds are not satisfied. The expression is not evaluated. Just like
+//! the error for when [no arm matches](#fallback), this error shows up in
+//! `cargo build`, but not in `cargo check`.
+//!
+//! ```compile_fail
+//! # use spez::spez_assert_impl;
+//! use std::fmt::Debug;
+//! struct NoDebugType;
+//! spez_assert_impl!(String::new(), Debug + Clone);
+//! spez_assert_impl!(NoDebugType, Debug); // ERROR
+//! ```
+//!
+//! # Which arm was used
+//!
+//! `spez_with_index!` works like `spez!`, but evaluates to a tuple of the value
+//! of the selected arm and the index of that arm as written, starting at zero.
+//! This can be useful to check in a test that the expected arm was used.
+//!
+//! ```
+//! # use spez::spez_with_index;
+//! let result = spez_with_index! {
+//!     for x = 1u8;
+//!     match u16 -> i32 { 0 }
+//!     match u8 -> i32 { 1 }
+//!     else -> i32 { 2 }
+//! };
+//! assert_eq!(result, (1, 1));
+//! ```
+//!
+//! # Names of the generated items
+//!
+//! The expansion of `spez!` defines a few types and traits, with names that
+//! start with `__Spez`. When reading the expanded code (e.g. with `cargo
+//! expand`), it can help to choose the prefix of these names yourself, with a
+//! `#[spez(prefix = "..")]` attribute before the `for`:
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     #[spez(prefix = "Describe")]
+//!     for x = 1;
+//!     match i32 -> &'static str { "i32" }
+//!     else -> &'static str { "other" }
+//! };
+//! assert_eq!(result, "i32");
+//! ```
+//!
+//! The generated names are then `DescribeMatch`, `DescribeMatch1`, and so on.
+//! Make sure not to use the same prefix for nested `spez!` invocations, or in
+//! a scope that already has items with such names.
+//!
+//! With the `debug-expansion` feature, the names of the traits for the arms
+//! also include their types, like `__SpezMatch1_Vec_u8`, and each of these
+//! traits gets a doc attribute describing its arm. This only changes names,
+//! not which arm is selected.
+//!
+//! # Capturing variables
+//!
+//! By default, you can't refer to variables of the scope around the `spez! {}` macro:
+//!
+//! ```compile_fail
+//! let a = 1;
+//! let result = spez! {
+//!     for x = 1;
+//!     match i32 {
+//!         println!("{}", a); // ERROR
+//!     }
+//! };
+//! ```
+//!
+//! Variables can be made available to the arms by listing them, with their
+//! types, in a `capture(..);` after the input. They are moved into the
+//! selected arm. (To capture a variable by reference, capture a reference to
+//! it.)
+//!
+//! ```
+//! # use spez::spez;
+//! let a = 1;
+//! let mut v = Vec::new();
+//! let r = &mut v;
+//! spez! {
+//!     for x = 2;
+//!     capture(a: i32, r: &mut Vec<i32>);
+//!     match i32 {
+//!         r.push(a + x);
+//!     }
+//!     else {}
+//! }
+//! assert_eq!(v, [3]);
+//! ```
+//!
+//! # Shared setup
+//!
+//! Statements that are needed by several arms can be put in a `prelude { .. }`
+//! after the input (and `capture(..);`). They run right before the body of the
+//! selected arm, after its guard, and can use the input and the captured
+//! variables.
+//!
+//! ```
+//! # use spez::spez;
+//! let result = spez! {
+//!     for x = 3;
+//!     prelude {
+//!         let unit = "cm";
+//!     }
+//!     match i32 -> String { format!("{} {}", x, unit) }
+//!     match f64 -> String { format!("{:.1} {}", x, unit) }
+//!     else -> String { String::new() }
+//! };
+//! assert_eq!(result, "3 cm");
+//! ```
+//!
+//! Types that are used by several arms can be given a shorter name with `type`
+//! aliases after that (and before the first arm). They can be used in the
+//! types, return types and bodies of all arms.
+//!
+//! ```
+//! # use spez::spez;
+//! use std::collections::HashMap;
+//! let map = HashMap::from([(String::from("a"), vec![1u8, 2])]);
+//! let result = spez! {
+//!     for x = map;
+//!     type M = HashMap<String, Vec<u8>>;
+//!     match M if x.is_empty() -> M { M::new() }
+//!     match M -> M { x }
+//! };
+//! assert_eq!(result["a"], [1, 2]);
+//! ```
+//!
+//! # In a generic function
+//!
+//! As mentioned above, the macro is of not much use in generic context, as the
+//! specialization is resolved based on the bounds rather than on the actual
+//! type in the instantiation of the generic function:
+//!
+//! ```
+//! # use spez::spez;
+//! # use std::fmt::Debug;
+//! fn f<T: Debug>(v: T) -> &'static str {
+//!     spez! {
+//!         for v;
+//!         match i32 -> &'static str {
+//!             ":)"
+//!         }
+//!         match<T: Debug> T -> &'static str {
+//!             ":("
+//!         }
+//!         match<T> T -> &'static str {
+//!             ":(("
+//!         }
+//!     }
+//! }
+//! assert_eq!(f(0i32), ":(");
+//! ```
+//!
+//! The arms are trait implementations, which are items of their own. Just like
+//! any other item inside a function, they can't use the generic parameters of
+//! that function:
+//!
+//! ```compile_fail
+//! # use spez::spez;
+//! fn f<U>() -> u8 {
+//!     spez! {
+//!         for 1;
+//!         match i32 where U: Copy -> u8 { 1 } // ERROR
+//!         else -> u8 { 0 }
+//!     }
+//! }
+//! ```
+//!
+//! To specialize on such a parameter, make it part of the input, for example
+//! with a `PhantomData`:
+//!
+//! ```
+//! # use spez::spez;
+//! use std::marker::PhantomData;
+//! fn f<U: Copy>() -> u8 {
+//!     spez! {
+//!         for (1, PhantomData::<U>);
+//!         match<V: Copy> (i32, PhantomData<V>) -> u8 { 1 }
+//!         else -> u8 { 0 }
+//!     }
+//! }
+//! assert_eq!(f::<u8>(), 1);
+//! ```
+//!
+//! # In a macro
+//!
+//! This is a demonstration of a macro that prints the `Debug` output of a
+//! value, but falls back to `"<object of type ...>"` if it doesn't implement
+//! `Debug`.
+//!
+//! ```
+//! # use spez::spez;
+//! # use std::fmt::Debug;
+//! macro_rules! debug {
+//!     ($e:expr) => {
+//!         spez! {
+//!             for x = $e;
+//!             match<T: Debug> T {
+//!                 println!("{:?}", x);
+//!             }
+//!             match<T> T {
+//!                 println!("<object of type {}>", std::any::type_name::<T>());
+//!             }
+//!         }
+//!     }
+//! }
+//! debug!(123);
+//! # struct NoDebugType;
+//! debug!(NoDebugType);
+//! ```
+//!
+//! A variant of this that returns a `String` is available as
+//! `spez_type_name!`. As it needs `std`, it is only available with the `std`
+//! feature, which is enabled by default. The code generated by the other
+//! macros only uses `core`, so they can be used in `#![no_std]` crates.
+//!
+//! ```
+//! # use spez::spez_type_name;
+//! # struct NoDebugType;
+//! assert_eq!(spez_type_name!(123), "123");
+//! assert!(spez_type_name!(NoDebugType).ends_with("NoDebugType"));
+//! ```
+//!
+//! Similarly, `try_clone!` clones a value only if its type implements `Clone`:
+//!
+//! ```
+//! # use spez::try_clone;
+//! # struct NoCloneType;
+//! assert_eq!(try_clone!(String::from("a")), Some(String::from("a")));
+//! assert!(try_clone!(NoCloneType).is_none());
+//! ```
+//!
+//! As this is a procedural macro crate, it can only export macros, not the
+//! functions generating their code. A procedural macro that wants to
+//! specialize on the type of an expression can emit a `::spez::spez! { .. }`
+//! invocation as part of its output instead.
+//!
+//! # Running a mutable trait method
+//!
+//! It is also possible to conditionally run a mutable trait method for an
+//! object, depending on whether or not that object implements a certain trait.
+//!
+//! ```
+//! # use spez::spez;
+//! #
+//! struct MyStruct1(u32);
+//! struct MyStruct2(u32);
+//!
+//! trait Increment {
+//!     fn inc(&mut self);
+//! }
+//!
+//! impl Increment for &mut MyStruct1 {
+//!     fn inc(&mut self) {
+//!         self.0 += 1;
+//!     }
+//! }
+//!
+//! let mut my_object1 = MyStruct1(0);
+//! let mut my_object2 = MyStruct2(0);
+//!
+//! assert_eq!(my_object1.0, 0);
+//! assert_eq!(my_object2.0, 0);
+//!
+//! spez! {
+//!     for x = &mut my_object1;
+//!     match<T> T where T: Increment {
+//!         x.inc();
+//!     }
+//!     match<T> T {}
+//! };
+//! spez! {
+//!     for x = &mut my_object2;
+//!     match<T> T where T: Increment {
+//!         x.inc();
+//!     }
+//!     match<T> T {}
+//! };
+//!
+//! assert_eq!(my_object1.0, 1);
+//! assert_eq!(my_object2.0, 0);
+//! ```
+//!
+//! Since the input is moved into the selected arm, this works the same for a
+//! `&mut` that is already stored in a variable, like `for r;` after `let r =
+//! &mut my_object1;`.
+//!
+//! # Supported Rust versions
+//!
+//! The macros need Rust 1.70 or newer. Some features expand to code that
+//! needs a newer compiler:
+//!
+//! - `spez_assert_impl!` and `#[spez(exhaustive(..))]` use `const { .. }`
+//!   blocks, which need Rust 1.79.
+//! - Arms returning `-> impl Trait`, `async for`, and `spez_fn!` use
+//!   `impl Trait + use<..>` in trait methods, which needs Rust 1.87.
+
+extern crate proc_macro;
+
+mod helpers;
+mod parse;
+
+use parse::{Args, Arm};
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::TokenTree;
+use quote::quote;
+use quote::quote_spanned;
+use std::collections::HashSet;
+use syn::spanned::Spanned;
+use syn::visit_mut;
+use syn::visit_mut::VisitMut;
+use syn::Token;
+
+/// Specialize based on the type of an expression.
+///
+/// See the [crate level documentation](index.html).
+#[proc_macro]
+pub fn spez(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	spez_impl(syn::parse_macro_input!(tokens), prefix, false, false).into()
+}
+
+/// Check whether the type of an expression satisfies some bounds.
+///
+/// `spez_matches!(expr, Bounds)` evaluates to `true` if the type of `expr`
+/// satisfies `Bounds`, and to `false` otherwise. The expression is borrowed,
+/// not consumed.
+///
+/// See the [crate level documentation](index.html#checking-for-a-trait).
+#[proc_macro]
+pub fn spez_matches(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	let args = syn::parse_macro_input!(tokens as helpers::MatchesArgs);
+	match args.into_args() {
+		Ok(args) => spez_impl(args, prefix, false, false),
+		Err(error) => error.to_compile_error(),
+	}
+	.into()
+}
+
+/// Check which of several bounds the type of an expression satisfies.
+///
+/// `spez_all_matches!(expr, [Bounds1, Bounds2, ...])` evaluates to a
+/// `[bool; N]`, with one element per bound, which is `true` if the type of
+/// `expr` satisfies that bound. Every bound is checked on its own. The
+/// expression is evaluated once, and borrowed, not consumed.
+///
+/// See the [crate level documentation](index.html#checking-for-a-trait).
+#[proc_macro]
+pub fn spez_all_matches(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	let name = fresh_ident(tokens.clone().into(), "__spez_value");
+	let args = syn::parse_macro_input!(tokens as helpers::AllMatchesArgs);
+	let expr = args.expr.clone();
+	match args.into_args(&name) {
+		Ok(args) => {
+			let probes = args
+				.into_iter()
+				.map(|args| spez_impl(args, prefix.clone(), false, false));
+			quote! {
+				{
+					let #name = &(#expr);
+					[#(#probes),*]
+				}
+			}
+		}
+		Err(error) => error.to_compile_error(),
+	}
+	.into()
+}
 
-extern crate proc_macro;
-
-mod parse;
+/// Assert that the type of an expression satisfies some bounds.
+///
+/// `spez_assert_impl!(expr, Bounds)` fails to compile if the type of `expr`
+/// does not satisfy `Bounds`. The expression is not evaluated.
+///
+/// See the [crate level documentation](index.html#checking-for-a-trait).
+#[proc_macro]
+pub fn spez_assert_impl(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	let args = syn::parse_macro_input!(tokens as helpers::MatchesArgs);
+	match args.into_assert_args() {
+		// The expression is only needed for its type.
+		Ok(args) => {
+			let expansion = spez_impl(args, prefix, false, false);
+			quote! {
+				if false {
+					#expansion
+				}
+			}
+		}
+		Err(error) => error.to_compile_error(),
+	}
+	.into()
+}
 
-use parse::Args;
-use proc_macro::TokenStream;
-use proc_macro2::Span;
-use proc_macro2::TokenStream as TokenStream2;
-use quote::quote;
+/// Specialize based on the type of an expression, without consuming it.
+///
+/// `spez_ref!` takes the same input as [`spez!`], but borrows the input, and
+/// adds the `&` to the types of all arms. In the bodies, the name of the input
+/// refers to the reference.
+///
+/// See the [crate level documentation](index.html#consuming-the-input).
+#[proc_macro]
+pub fn spez_ref(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	let args = syn::parse_macro_input!(tokens as Args);
+	match helpers::ref_args(args) {
+		Ok(args) => spez_impl(args, prefix, false, false),
+		Err(error) => error.to_compile_error(),
+	}
+	.into()
+}
 
-/// Specialize based on the type of an expression.
+/// Describe a value, by its `Debug` output or else by the name of its type.
 ///
-/// See the [crate level documentation](index.html).
+/// `spez_type_name!(expr)` evaluates to a `String`. If the type of `expr`
+/// implements `Debug`, this is `format!("{:?}", expr)`. Otherwise, it is the
+/// name of the type, as given by `std::any::type_name`. The expression is
+/// borrowed, not consumed.
+///
+/// This macro is only available with the `std` feature, which is enabled by
+/// default.
+#[cfg(feature = "std")]
 #[proc_macro]
-pub fn spez(tokens: TokenStream) -> TokenStream {
-	spez_impl(syn::parse_macro_input!(tokens)).into()
+pub fn spez_type_name(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	let expr = syn::parse_macro_input!(tokens as syn::Expr);
+	match helpers::type_name_args(expr) {
+		Ok(args) => spez_impl(args, prefix, false, false),
+		Err(error) => error.to_compile_error(),
+	}
+	.into()
 }
 
-fn refs(n: usize, is_mutable: bool) -> TokenStream2 {
-	let mut refs = TokenStream2::new();
-	for _ in 0..n {
-		if !is_mutable {
-			refs.extend(quote![&]);
-		} else {
-			refs.extend(quote![&mut]);
-		}
+/// Clone a value, if its type implements `Clone`.
+///
+/// `try_clone!(expr)` evaluates to `Some` with a clone of the value of
+/// `expr` if its type implements `Clone`, and to `None` otherwise. The
+/// expression is borrowed, not consumed.
+#[proc_macro]
+pub fn try_clone(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	let expr = syn::parse_macro_input!(tokens as syn::Expr);
+	match helpers::try_clone_args(expr) {
+		Ok(args) => spez_impl(args, prefix, false, false),
+		Err(error) => error.to_compile_error(),
 	}
-	refs
+	.into()
 }
 
-fn spez_impl(args: Args) -> TokenStream2 {
-	let mut traits = TokenStream2::new();
+/// Specialize based on the type of an expression, and return which arm was used.
+///
+/// `spez_with_index!` takes the same input as [`spez!`], but evaluates to a
+/// tuple of the value of the selected arm and the (zero-based) index of that
+/// arm, as written.
+///
+/// See the [crate level documentation](index.html#which-arm-was-used).
+#[proc_macro]
+pub fn spez_with_index(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	spez_impl(syn::parse_macro_input!(tokens), prefix, true, false).into()
+}
+
+/// Specialize based on the type of an expression, and return a closure that runs the selected arm.
+///
+/// `spez_fn!` takes the same input as [`spez!`], but evaluates to an
+/// `impl FnOnce() -> R`, where `R` is the return type of the selected arm. The
+/// input is consumed right away, but the body only runs when the closure is
+/// called.
+///
+/// See the [crate level documentation](index.html#deferred-evaluation).
+#[proc_macro]
+pub fn spez_fn(tokens: TokenStream) -> TokenStream {
+	let prefix = fresh_prefix(tokens.clone().into(), "__Spez");
+	spez_impl(syn::parse_macro_input!(tokens), prefix, false, true).into()
+}
+
+/// Generate a macro that wraps a value in the variant of an enum that holds its type.
+///
+/// For an enum with only variants like `Name(Type)`, this defines a macro
+/// that takes an expression, and evaluates to `Some` with the value of that
+/// expression in the variant that holds its type, or to `None` if there is no
+/// such variant. The macro is named after the enum in snake case, or as given
+/// by `#[spez(name = "..")]`. `#[spez(crate = path)]` sets the path to this
+/// crate, which is `::spez` by default.
+///
+/// See the [crate level documentation](index.html#dispatching-to-an-enum).
+#[proc_macro_derive(Spez, attributes(spez))]
+pub fn derive_spez(tokens: TokenStream) -> TokenStream {
+	let input = syn::parse_macro_input!(tokens as syn::DeriveInput);
+	match helpers::derive_spez(input) {
+		Ok(tokens) => tokens,
+		Err(error) => error.to_compile_error(),
+	}
+	.into()
+}
 
-	let param_def = match args.param {
-		Some(param) => quote! {
-			#[allow(unused_mut)]
-			let mut #param = self.0.take().unwrap();
-			let _ = #param; // Suppress unused variable warning.
+/// Returns the maximum number of arms, as set by the `SPEZ_MAX_ARMS`
+/// environment variable, if it is set.
+fn max_arms() -> Result<Option<usize>, String> {
+	match std::env::var("SPEZ_MAX_ARMS") {
+		Ok(max) => match max.trim().parse() {
+			Ok(max) => Ok(Some(max)),
+			Err(_) => Err(format!("`SPEZ_MAX_ARMS` must be a number, not `{}`", max)),
 		},
-		None => quote! {},
+		Err(std::env::VarError::NotPresent) => Ok(None),
+		Err(std::env::VarError::NotUnicode(_)) => {
+			Err(String::from("`SPEZ_MAX_ARMS` must be a number"))
+		}
+	}
+}
+
+/// Turns the tokens of a type into something that can be part of an identifier.
+fn sanitize(ty: &str) -> String {
+	let name = ty
+		.split(|c: char| !c.is_ascii_alphanumeric())
+		.filter(|part| !part.is_empty())
+		.collect::<Vec<_>>()
+		.join("_");
+	match name.is_empty() {
+		true => String::from("_"),
+		false => name,
+	}
+}
+
+/// Returns a generic parameter as it would be used as an argument.
+fn generic_arg(param: &syn::GenericParam) -> TokenStream2 {
+	match param {
+		syn::GenericParam::Lifetime(param) => {
+			let lifetime = &param.lifetime;
+			quote! { #lifetime }
+		}
+		syn::GenericParam::Type(syn::TypeParam { ident, .. })
+		| syn::GenericParam::Const(syn::ConstParam { ident, .. }) => quote! { #ident },
+	}
+}
+
+/// Returns all identifiers that occur in `tokens`.
+fn idents(tokens: TokenStream2) -> HashSet<String> {
+	fn collect(tokens: TokenStream2, idents: &mut HashSet<String>) {
+		for token in tokens {
+			match token {
+				TokenTree::Ident(ident) => {
+					let ident = ident.to_string();
+					idents.insert(ident.strip_prefix("r#").unwrap_or(&ident).to_string());
+				}
+				TokenTree::Group(group) => collect(group.stream(), idents),
+				_ => {}
+			}
+		}
+	}
+	let mut idents = HashSet::new();
+	collect(tokens, &mut idents);
+	idents
+}
+
+/// Returns `prefix`, or `prefix` followed by a number, such that `f` returns true for it.
+fn find_name(prefix: &str, f: impl Fn(&str) -> bool) -> String {
+	(0..)
+		.map(|i| match i {
+			0 => prefix.to_string(),
+			i => format!("{}{}", prefix, i),
+		})
+		.find(|name| f(name))
+		.unwrap()
+}
+
+/// Creates an identifier starting with `prefix` that does not occur anywhere in `tokens`.
+fn fresh_ident(tokens: TokenStream2, prefix: &str) -> syn::Ident {
+	let idents = idents(tokens);
+	let name = find_name(prefix, |name| !idents.contains(name));
+	syn::Ident::new(&name, Span::mixed_site())
+}
+
+/// Creates a prefix starting with `prefix` that no identifier in `tokens` starts with.
+///
+/// Any name starting with this prefix can't clash with a name given by the user.
+fn fresh_prefix(tokens: TokenStream2, prefix: &str) -> String {
+	let idents = idents(tokens);
+	find_name(prefix, |name| {
+		!idents.iter().any(|ident| ident.starts_with(name))
+	})
+}
+
+/// Replaces elided lifetimes in `ty` by new lifetime parameters in `generics`,
+/// so they can be named.
+///
+/// `context` should contain all tokens of the arm, to make sure the new names
+/// don't clash with anything.
+fn name_elided_lifetimes(ty: &mut syn::Type, generics: &mut syn::Generics, context: TokenStream2) {
+	struct Visitor {
+		context: TokenStream2,
+		lifetimes: Vec<syn::Lifetime>,
+	}
+	impl Visitor {
+		fn fresh(&mut self, span: Span) -> syn::Lifetime {
+			let context = &self.context;
+			let lifetimes = &self.lifetimes;
+			let ident = fresh_ident(quote! { #context #(#lifetimes)* }, "__spez");
+			let lifetime = syn::Lifetime {
+				apostrophe: span,
+				ident,
+			};
+			self.lifetimes.push(lifetime.clone());
+			lifetime
+		}
+	}
+	impl VisitMut for Visitor {
+		fn visit_type_reference_mut(&mut self, ty: &mut syn::TypeReference) {
+			if ty.lifetime.is_none() {
+				ty.lifetime = Some(self.fresh(ty.and_token.span));
+			}
+			visit_mut::visit_type_reference_mut(self, ty);
+		}
+		fn visit_lifetime_mut(&mut self, lifetime: &mut syn::Lifetime) {
+			if lifetime.ident == "_" {
+				*lifetime = self.fresh(lifetime.apostrophe);
+			}
+		}
+		// Elided lifetimes in these are not lifetime parameters of the impl.
+		fn visit_type_bare_fn_mut(&mut self, _: &mut syn::TypeBareFn) {}
+		fn visit_parenthesized_generic_arguments_mut(
+			&mut self,
+			_: &mut syn::ParenthesizedGenericArguments,
+		) {
+		}
+	}
+	let mut visitor = Visitor {
+		context,
+		lifetimes: Vec::new(),
 	};
+	visitor.visit_type_mut(ty);
+	for (i, lifetime) in visitor.lifetimes.into_iter().enumerate() {
+		generics.params.insert(
+			i,
+			syn::GenericParam::Lifetime(syn::LifetimeParam::new(lifetime)),
+		);
+	}
+}
+
+/// Replaces elided lifetimes in `ty` by `lifetime`, like the elision rules do
+/// for the return type of a function, and returns whether there were any.
+///
+/// Without a `lifetime`, this only checks for elided lifetimes.
+fn fill_elided_lifetimes(ty: &mut syn::Type, lifetime: Option<&syn::Lifetime>) -> bool {
+	struct Visitor<'a> {
+		lifetime: Option<&'a syn::Lifetime>,
+		found: bool,
+	}
+	impl VisitMut for Visitor<'_> {
+		fn visit_type_reference_mut(&mut self, ty: &mut syn::TypeReference) {
+			if ty.lifetime.is_none() {
+				self.found = true;
+				ty.lifetime = self.lifetime.cloned();
+			}
+			visit_mut::visit_type_reference_mut(self, ty);
+		}
+		fn visit_lifetime_mut(&mut self, lifetime: &mut syn::Lifetime) {
+			if lifetime.ident == "_" {
+				self.found = true;
+				if let Some(replacement) = self.lifetime {
+					*lifetime = replacement.clone();
+				}
+			}
+		}
+		// Elided lifetimes in these have their own meaning.
+		fn visit_type_bare_fn_mut(&mut self, _: &mut syn::TypeBareFn) {}
+		fn visit_parenthesized_generic_arguments_mut(
+			&mut self,
+			_: &mut syn::ParenthesizedGenericArguments,
+		) {
+		}
+	}
+	let mut visitor = Visitor {
+		lifetime,
+		found: false,
+	};
+	visitor.visit_type_mut(ty);
+	visitor.found
+}
+
+/// Returns the distinct lifetimes that occur in `ty`.
+fn lifetimes(ty: &syn::Type) -> Vec<syn::Lifetime> {
+	struct Visitor(Vec<syn::Lifetime>);
+	impl VisitMut for Visitor {
+		fn visit_lifetime_mut(&mut self, lifetime: &mut syn::Lifetime) {
+			if !self.0.contains(lifetime) {
+				self.0.push(lifetime.clone());
+			}
+		}
+	}
+	let mut visitor = Visitor(Vec::new());
+	visitor.visit_type_mut(&mut ty.clone());
+	visitor.0
+}
+
+/// Replaces `Self` in `ty` by `replacement`.
+fn replace_self(ty: &mut syn::Type, replacement: &syn::Type) {
+	struct Visitor<'a>(&'a syn::Type);
+	impl VisitMut for Visitor<'_> {
+		fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+			match ty {
+				syn::Type::Path(path) if path.qself.is_none() && path.path.is_ident("Self") => {
+					*ty = self.0.clone();
+				}
+				ty => visit_mut::visit_type_mut(self, ty),
+			}
+		}
+	}
+	Visitor(replacement).visit_type_mut(ty);
+}
+
+/// Checks whether `attrs` contain a `warn`, `deny` or `forbid` of `unused_variables`.
+fn warns_unused<'a>(attrs: impl IntoIterator<Item = &'a syn::Attribute>) -> bool {
+	attrs.into_iter().any(|attr| {
+		let path = attr.path();
+		let meta = &attr.meta;
+		(path.is_ident("warn") || path.is_ident("deny") || path.is_ident("forbid"))
+			&& idents(quote! { #meta })
+				.iter()
+				.any(|lint| lint == "unused_variables" || lint == "unused")
+	})
+}
+
+/// Checks whether `tokens` may use any of the names bound by `pat`.
+///
+/// A string literal containing the name counts as well, as it might be a
+/// format string that uses it.
+fn mentions(pat: &syn::Pat, tokens: TokenStream2) -> bool {
+	fn literals(tokens: TokenStream2, strings: &mut Vec<String>) {
+		for token in tokens {
+			match token {
+				TokenTree::Literal(literal) => strings.push(literal.to_string()),
+				TokenTree::Group(group) => literals(group.stream(), strings),
+				_ => {}
+			}
+		}
+	}
+	let names = idents(quote! { #pat });
+	let mut strings = Vec::new();
+	literals(tokens.clone(), &mut strings);
+	let used = idents(tokens);
+	names
+		.iter()
+		.any(|name| used.contains(name) || strings.iter().any(|s| s.contains(name.as_str())))
+}
 
-	let is_mutable = match args.expr {
-		syn::Expr::Reference(ref refer) => refer.mutability.is_some(),
+/// Checks whether an arm unconditionally matches any type.
+fn is_catch_all(arm: &Arm) -> bool {
+	if arm.guard.is_some()
+		|| arm.generics.where_clause.is_some()
+		|| arm.attrs.iter().any(|attr| attr.path().is_ident("cfg"))
+	{
+		return false;
+	}
+	arm.tys.iter().any(|ty| match ty {
+		syn::Type::Infer(_) => true,
+		syn::Type::Path(ty) if ty.qself.is_none() => arm.generics.params.iter().any(|param| {
+			matches!(param, syn::GenericParam::Type(param)
+				if param.bounds.is_empty() && ty.path.is_ident(&param.ident))
+		}),
 		_ => false,
+	})
+}
+
+/// Checks whether a pattern for the input can be used for an arm matching `ty`.
+///
+/// Tuple patterns only fit tuple types of the same length, or references to
+/// them, in which case the names are bound to references.
+fn pat_fits(pat: &syn::Pat, ty: &syn::Type) -> bool {
+	match (pat, ty) {
+		(syn::Pat::Tuple(pat), syn::Type::Tuple(ty)) => {
+			pat.elems.len() == ty.elems.len()
+				&& pat.elems.iter().zip(&ty.elems).all(|(p, t)| pat_fits(p, t))
+		}
+		(syn::Pat::Tuple(_), syn::Type::Paren(ty)) => pat_fits(pat, &ty.elem),
+		(syn::Pat::Tuple(_), syn::Type::Reference(ty)) => pat_fits(pat, &ty.elem),
+		(syn::Pat::Tuple(_), _) => false,
+		_ => true,
+	}
+}
+
+/// Converts a pattern binding names back into an expression using those names.
+fn pat_to_expr(pat: &syn::Pat) -> Option<TokenStream2> {
+	match pat {
+		syn::Pat::Ident(pat) if pat.subpat.is_none() => {
+			let ident = &pat.ident;
+			Some(quote! { #ident })
+		}
+		syn::Pat::Tuple(pat) => {
+			let elems = pat
+				.elems
+				.iter()
+				.map(pat_to_expr)
+				.collect::<Option<Vec<_>>>()?;
+			Some(quote! { (#(#elems,)*) })
+		}
+		syn::Pat::TupleStruct(pat) if pat.qself.is_none() => {
+			let path = &pat.path;
+			let elems = pat
+				.elems
+				.iter()
+				.map(pat_to_expr)
+				.collect::<Option<Vec<_>>>()?;
+			Some(quote! { #path(#(#elems),*) })
+		}
+		syn::Pat::Struct(pat) if pat.qself.is_none() && pat.rest.is_none() => {
+			let path = &pat.path;
+			let fields = pat
+				.fields
+				.iter()
+				.map(|field| {
+					let member = &field.member;
+					let expr = pat_to_expr(&field.pat)?;
+					Some(quote! { #member: #expr })
+				})
+				.collect::<Option<Vec<_>>>()?;
+			Some(quote! { #path { #(#fields),* } })
+		}
+		syn::Pat::Paren(pat) => pat_to_expr(&pat.pat),
+		_ => None,
+	}
+}
+
+/// Removes `?Sized` from the bounds of `ty`, if it is a type parameter.
+///
+/// The input is stored by value, so it is always sized, and the generated
+/// types require that.
+fn remove_maybe_sized(ty: &syn::Type, generics: &mut syn::Generics) {
+	let syn::Type::Path(syn::TypePath { qself: None, path }) = ty else {
+		return;
+	};
+	let Some(ident) = path.get_ident() else {
+		return;
+	};
+	let is_param = |ty: &syn::Type| matches!(ty, syn::Type::Path(ty) if ty.qself.is_none() && ty.path.is_ident(ident));
+	let is_maybe_sized = |bound: &syn::TypeParamBound| {
+		matches!(bound, syn::TypeParamBound::Trait(bound)
+			if matches!(bound.modifier, syn::TraitBoundModifier::Maybe(_)))
+	};
+	for param in generics.type_params_mut() {
+		if param.ident == *ident {
+			param.bounds = std::mem::take(&mut param.bounds)
+				.into_iter()
+				.filter(|bound| !is_maybe_sized(bound))
+				.collect();
+		}
+	}
+	for predicate in generics
+		.where_clause
+		.iter_mut()
+		.flat_map(|w| &mut w.predicates)
+	{
+		if let syn::WherePredicate::Type(predicate) = predicate {
+			if is_param(&predicate.bounded_ty) {
+				predicate.bounds = std::mem::take(&mut predicate.bounds)
+					.into_iter()
+					.filter(|bound| !is_maybe_sized(bound))
+					.collect();
+			}
+		}
+	}
+}
+
+/// Adds a `for<'_>` binder to all predicates that don't have one.
+///
+/// Bounds on concrete types are checked eagerly and result in an error if they
+/// don't hold. With a binder, they simply make the impl not apply.
+fn make_non_trivial(where_clause: &mut syn::WhereClause) {
+	for predicate in &mut where_clause.predicates {
+		if let syn::WherePredicate::Type(predicate) = predicate {
+			if predicate.lifetimes.is_none() {
+				predicate.lifetimes = Some(syn::parse_quote! { for<'__spez> });
+			}
+		}
+	}
+}
+
+fn spez_impl(args: Args, prefix: String, with_index: bool, is_deferred: bool) -> TokenStream2 {
+	// An `async` body already only runs once the future is polled, so the
+	// closure only has to hand out the future.
+	if args.async_token.is_some() && is_deferred {
+		let future = syn::Ident::new("__spez_future", Span::mixed_site());
+		let expansion = spez_impl(args, prefix, with_index, false);
+		return quote! {
+			{
+				let #future = #expansion;
+				move || #future
+			}
+		};
+	}
+	if let (Some(track_caller), true) = (&args.track_caller, args.async_token.is_some()) {
+		return syn::Error::new_spanned(
+			track_caller,
+			"`#[spez(track_caller)]` can not be used with `async`",
+		)
+		.to_compile_error();
+	}
+	if let (Some(track_caller), true) = (&args.track_caller, is_deferred) {
+		return syn::Error::new_spanned(
+			track_caller,
+			"`#[spez(track_caller)]` can not be used with spez_fn!",
+		)
+		.to_compile_error();
+	}
+	if let (Some(in_token), true) = (&args.each, args.async_token.is_some()) {
+		return syn::Error::new_spanned(in_token, "`for .. in [..]` can not be used with `async`")
+			.to_compile_error();
+	}
+	if let (Some(in_token), true) = (&args.each, is_deferred) {
+		return syn::Error::new_spanned(in_token, "`for .. in [..]` can not be used with spez_fn!")
+			.to_compile_error();
+	}
+	let mut arms = args.arms;
+	match max_arms() {
+		Ok(Some(max)) if args.arm_spans.len() > max => {
+			// Point at the first arm that is over the limit.
+			return syn::Error::new(
+				args.arm_spans[max],
+				format!(
+					"this spez! has {} arms, more than the {} allowed by `SPEZ_MAX_ARMS`; \
+					 consider splitting it into several smaller ones",
+					args.arm_spans.len(),
+					max,
+				),
+			)
+			.to_compile_error();
+		}
+		Ok(_) => {}
+		Err(error) => return syn::Error::new(Span::call_site(), error).to_compile_error(),
+	}
+	if let Some(return_type) = args.return_type {
+		for arm in &mut arms {
+			arm.return_type.get_or_insert_with(|| return_type.clone());
+		}
+	}
+	// Parentheses that were only needed to separate the input from the
+	// return type would cause an `unused_parens` warning.
+	let expr = match args.expr {
+		syn::Expr::Paren(expr) if expr.attrs.is_empty() => *expr.expr,
+		expr => expr,
+	};
+	let mode = Mode {
+		is_async: args.async_token.is_some(),
+		is_deferred,
+		track_caller: args.track_caller.is_some(),
+		borrow: args.borrow,
+		prefix: match args.prefix {
+			Some(prefix) => prefix.to_string(),
+			None => prefix,
+		},
+		captures: args.captures,
+		prelude: args.prelude,
+		with_index,
+	};
+	let input = match expr {
+		syn::Expr::Array(array) if args.each.is_some() => {
+			Input::Each(array.elems.iter().map(|expr| quote! { #expr }).collect())
+		}
+		expr => Input::One(quote! { #expr }),
+	};
+	let expansion = expand(args.param.as_ref(), input, &arms, &mode);
+	if args.exhaustive.is_empty() && args.aliases.is_empty() {
+		return expansion;
+	}
+	let probes = match args.exhaustive.is_empty() {
+		true => None,
+		false => {
+			let probes = args
+				.exhaustive
+				.iter()
+				.map(|ty| exhaustive_probe(ty, &arms, &mode));
+			Some(quote! {
+				if false {
+					#(#probes)*
+				}
+			})
+		}
+	};
+	// The aliases are items in the outermost block, so every arm can see them.
+	// An alias that is only used by arms that are not selected counts as
+	// unused.
+	let aliases = &args.aliases;
+	quote! {
+		{
+			#(
+				#[allow(dead_code)]
+				#aliases
+			)*
+			#probes
+			#expansion
+		}
+	}
+}
+
+/// Generates a `spez!` on a value of type `ty` that fails to compile if only
+/// the `else` arm matches it, for `#[spez(exhaustive(..))]`.
+///
+/// Guards can fall through at run time, so arms with a guard are left out. As
+/// with `spez_assert_impl!`, the error is reported when the generated `else`
+/// arm is instantiated.
+fn exhaustive_probe(ty: &syn::Type, arms: &[Arm], mode: &Mode) -> TokenStream2 {
+	let mut probe_arms = Vec::new();
+	for arm in arms {
+		if arm.fallback.is_some() || arm.guard.is_some() {
+			continue;
+		}
+		probe_arms.push(Arm {
+			attrs: arm.attrs.clone(),
+			fallback: None,
+			generics: arm.generics.clone(),
+			tys: arm.tys.clone(),
+			binding: None,
+			not_bound: None,
+			guard: None,
+			return_type: None,
+			body_attrs: Vec::new(),
+			body: syn::parse_quote! { {} },
+			use_trait: None,
+			priority: None,
+			index: 0,
+		});
+		if is_catch_all(arm) {
+			break;
+		}
+	}
+	if !probe_arms.iter().any(is_catch_all) {
+		// The error should point at the type that is missing.
+		let error = quote_spanned! {ty.span()=>
+			const {
+				::core::panic!(::core::concat!(
+					"`",
+					::core::stringify!(#ty),
+					"` is not matched by any arm of spez!",
+				))
+			}
+		};
+		probe_arms.push(syn::parse_quote! { else { #error } });
+	}
+	let probe = syn::Ident::new("__spez_probe", Span::mixed_site());
+	let borrow = mode
+		.borrow
+		.as_ref()
+		.map(|(and_token, mutability)| quote! { #and_token #mutability });
+	let expansion = expand(
+		None,
+		Input::One(quote! { ::core::option::Option::unwrap(#probe) }),
+		&probe_arms,
+		&Mode {
+			is_async: false,
+			is_deferred: false,
+			captures: Vec::new(),
+			prelude: Vec::new(),
+			with_index: false,
+			..mode.clone()
+		},
+	);
+	quote! {
+		let #probe: ::core::option::Option<#borrow #ty> = ::core::option::Option::None;
+		#expansion;
+	}
+}
+
+/// Settings that apply to all arms.
+#[derive(Clone)]
+struct Mode {
+	/// Whether the bodies are `async`.
+	is_async: bool,
+	/// Whether the bodies are put in a closure, to run when it is called.
+	is_deferred: bool,
+	/// Whether the methods report the location of their caller when they
+	/// panic.
+	track_caller: bool,
+	/// The `&` or `&mut` to prepend to the types of all arms.
+	borrow: Option<parse::Borrow>,
+	/// The prefix for the names of the generated items.
+	prefix: String,
+	/// The variables that are moved into the selected arm, with their types.
+	captures: Vec<(syn::Ident, syn::Type)>,
+	/// The statements that run before the body of the selected arm.
+	prelude: Vec<syn::Stmt>,
+	/// Whether the arms return their index along with their value.
+	with_index: bool,
+}
+
+impl Mode {
+	/// The name of a generated item.
+	///
+	/// These don't come from the input, so they get a `mixed_site` span, like
+	/// the other names that are internal to the expansion.
+	fn ident(&self, name: &str) -> syn::Ident {
+		syn::Ident::new(&format!("{}{}", self.prefix, name), Span::mixed_site())
+	}
+
+	/// The parameter of the generated methods that takes the captured variables.
+	fn captures_param(&self) -> TokenStream2 {
+		if self.captures.is_empty() {
+			return quote! {};
+		}
+		let tys = self.captures.iter().map(|(_, ty)| ty);
+		let captures = syn::Ident::new("__spez_captures", Span::mixed_site());
+		quote! { , #captures: (#(#tys,)*) }
+	}
+
+	/// The argument that passes the captured variables to the generated methods.
+	fn captures_arg(&self) -> TokenStream2 {
+		if self.captures.is_empty() {
+			return quote! {};
+		}
+		let idents = self.captures.iter().map(|(ident, _)| ident);
+		quote! { (#(#idents,)*) }
+	}
+
+	/// The statements of the prelude, as they are put before the body of an arm.
+	fn prelude(&self) -> TokenStream2 {
+		let stmts = self.prelude.iter().map(|stmt| match stmt {
+			// Not every arm uses every binding of the prelude.
+			syn::Stmt::Local(_) => quote! {
+				#[allow(unused_variables)]
+				#stmt
+			},
+			stmt => quote! { #stmt },
+		});
+		quote! { #(#stmts)* }
+	}
+
+	/// Makes the captured variables available in a generated method.
+	fn captures_def(&self) -> TokenStream2 {
+		if self.captures.is_empty() {
+			return quote! {};
+		}
+		let idents = self.captures.iter().map(|(ident, _)| ident);
+		let captures = syn::Ident::new("__spez_captures", Span::mixed_site());
+		quote! {
+			#[allow(unused_mut, unused_variables)]
+			let (#(mut #idents,)*) = #captures;
+		}
+	}
+}
+
+fn expand(param: Option<&syn::Pat>, input: Input, arms: &[Arm], mode: &Mode) -> TokenStream2 {
+	// Layer 0 holds the input, and every next layer holds the previous one,
+	// and derefs to it.
+	//
+	// This makes the number of auto-deref steps grow linearly with the number
+	// of arms. Method resolution tries the steps one by one, and the first one
+	// with a matching impl wins, so every arm needs a step of its own to keep
+	// the arms ordered. Grouping arms onto the same step would make them
+	// ambiguous whenever more than one of them matches.
+	let layer = |depth: usize| match depth {
+		0 => mode.ident("Match"),
+		depth => mode.ident(&format!("Layer{}", depth)),
+	};
+	let mut traits = TokenStream2::new();
+	let captures_param = mode.captures_param();
+	let captures_def = mode.captures_def();
+	let captures_arg = mode.captures_arg();
+	let prelude = mode.prelude();
+
+	let n_arms = arms.len();
+
+	// The methods can only take `&self`, as auto-deref only produces
+	// references. Moving the input out of that without `unsafe` (which would
+	// break crates with `#![forbid(unsafe_code)]`) needs the `Cell`. After
+	// inlining, the `take()` and `unwrap()` usually optimize away.
+	//
+	// Only the arms at `depth` or below can be selected for a receiver with
+	// `depth` layers.
+	let receiver = |expr: &TokenStream2, depth: usize| {
+		let match_struct = layer(0);
+		let mut receiver =
+			quote! { #match_struct(::core::cell::Cell::new(::core::option::Option::Some(#expr))) };
+		for depth in 1..=depth {
+			let outer = layer(depth);
+			receiver = quote! { #outer(#receiver) };
+		}
+		receiver
 	};
 
-	let n_arms = args.arms.len();
+	// Arms after one that matches any type can never be selected. Using a
+	// deprecated item in them produces a warning, which can be silenced with
+	// `#[allow(deprecated)]` on the arm.
+	let catch_all = arms.iter().position(is_catch_all);
+	let unreachable = mode.ident("Unreachable");
+	if catch_all.is_some_and(|catch_all| catch_all + 1 < n_arms) {
+		traits.extend(quote! {
+			#[deprecated(note = "this arm is unreachable, as an earlier arm matches any type")]
+			struct #unreachable;
+		});
+	}
 
-	for (i, arm) in args.arms.into_iter().enumerate() {
-		let name = syn::Ident::new(&format!("Match{}", i + 1), Span::call_site());
-		let body = arm.body;
-		let ty = arm.ty;
-		let generics = &arm.generics;
-		let where_clause = &arm.generics.where_clause;
-		let refs = refs(n_arms - i - 1, is_mutable);
-		let return_type = match arm.return_type {
+	for (i, arm) in arms.iter().enumerate() {
+		let (name, description) = match cfg!(feature = "debug-expansion") {
+			true => {
+				let tys = &arm.tys;
+				let tys = quote! { #(#tys)|* }.to_string();
+				let name = mode.ident(&format!("Match{}_{}", i + 1, sanitize(&tys)));
+				let description = format!(" The arm for `{}`.", tys);
+				(name, Some(quote! { #[doc = #description] }))
+			}
+			false => (mode.ident(&format!("Match{}", i + 1)), None),
+		};
+		let unreachable = catch_all.filter(|&catch_all| i > catch_all).map(|_| {
+			let unreachable = syn::Ident::new(&unreachable.to_string(), arm.tys[0].span());
+			quote! { let _ = #unreachable; }
+		});
+		// Attributes that only apply to functions go on the method, the others
+		// on the impl.
+		let (fn_attrs, attrs): (Vec<_>, Vec<_>) = arm.attrs.iter().partition(|attr| {
+			["inline", "cold", "track_caller"]
+				.iter()
+				.any(|name| attr.path().is_ident(name))
+		});
+		let has_fn_attr = |name| fn_attrs.iter().any(|attr| attr.path().is_ident(name));
+		let arm_param = arm.binding.as_ref().or(param);
+		// Without a name, the input is still moved out, so it gets dropped at
+		// the end of the arm, and a guard can pass it on.
+		let hidden_param: syn::Pat = {
+			let ident = syn::Ident::new("__spez_input", Span::mixed_site());
+			syn::parse_quote! { #ident }
+		};
+		let body_attrs = &arm.body_attrs;
+		let body = &arm.body.stmts;
+		// Layer 0 is used for the error when no arm matches.
+		let depth = n_arms - i;
+		let take = {
+			let derefs = (0..=depth).map(|_| quote! { * });
+			quote! { (#(#derefs)* self).0.take().unwrap() }
+		};
+		let return_type = match &arm.return_type {
 			Some(return_type) => quote! { #return_type },
 			None => quote! { () },
 		};
 
+		// `impl Trait` and `!` can't be used as an associated type, so those
+		// are put directly on the method instead.
+		let is_impl_trait = matches!(arm.return_type, Some(syn::Type::ImplTrait(_)));
+		let is_never = matches!(arm.return_type, Some(syn::Type::Never(_)));
+		if is_never && mode.is_async {
+			return syn::Error::new_spanned(
+				&arm.return_type,
+				"`!` can not be used as the return type in an `async` spez!",
+			)
+			.to_compile_error();
+		}
+		if (is_never || is_impl_trait) && mode.is_deferred {
+			return syn::Error::new_spanned(
+				&arm.return_type,
+				"`!` and `impl Trait` can not be used as the return type in spez_fn!",
+			)
+			.to_compile_error();
+		}
+		// `Self` in the return type stands for the type of the arm, and a body
+		// of just `self` for the input.
+		let returns_self = !is_impl_trait
+			&& arm
+				.return_type
+				.as_ref()
+				.is_some_and(|ty| idents(quote! { #ty }).contains("Self"));
+		// Elided lifetimes in the return type get the lifetime of the type of
+		// the arm, if it has exactly one, just like for a function.
+		let returns_elided = !is_impl_trait
+			&& arm
+				.return_type
+				.clone()
+				.is_some_and(|mut ty| fill_elided_lifetimes(&mut ty, None));
+		let returns_input = matches!(&body[..], [syn::Stmt::Expr(syn::Expr::Path(path), None)]
+			if path.attrs.is_empty() && path.qself.is_none() && path.path.is_ident("self"));
+
+		// Methods returning an opaque type get the generic parameters of the
+		// arm added to the trait, so they can be used in the return type. The
+		// return type must not capture the lifetime of `&self`, since that
+		// refers to a temporary.
+		let is_opaque = is_impl_trait || mode.is_async || mode.is_deferred;
+		let opaque = |captures: TokenStream2| {
+			let output = match (is_impl_trait, mode.with_index) {
+				(true, false) => quote! { #return_type + #captures },
+				(true, true) => quote! { (#return_type + #captures, usize) },
+				(false, _) => quote! { Self::Return },
+			};
+			if mode.is_async {
+				quote! { impl ::core::future::Future<Output = #output> + #captures }
+			} else if mode.is_deferred {
+				quote! { impl ::core::ops::FnOnce() -> #output + #captures }
+			} else {
+				output
+			}
+		};
+		let trait_args = arm.generics.params.iter().map(generic_arg);
+		let trait_args = quote! { <#(#trait_args),*> };
+		let assoc_type = !is_impl_trait && !is_never;
+
+		let trait_params = arm.generics.params.iter().map(|param| match param {
+			syn::GenericParam::Const(param) => {
+				let ident = &param.ident;
+				let ty = &param.ty;
+				quote! { const #ident: #ty }
+			}
+			param => generic_arg(param),
+		});
+		let (trait_params, decl_return_type) = if is_never {
+			(quote! {}, quote! { ! })
+		} else if is_opaque {
+			let args = arm.generics.params.iter().map(generic_arg);
+			(
+				quote! { <#(#trait_params),*> },
+				opaque(quote! { use<#(#args,)* Self> }),
+			)
+		} else {
+			(quote! {}, quote! { Self::Return })
+		};
+		let decl_assoc_type = match assoc_type {
+			true => quote! { type Return; },
+			false => quote! {},
+		};
 		traits.extend(quote! {
-			trait #name {
-				type Return;
-				fn spez(&self) -> Self::Return;
+			#description
+			trait #name #trait_params {
+				#decl_assoc_type
+				fn spez(&self #captures_param) -> #decl_return_type;
+			}
+		});
+
+		for ty in &arm.tys {
+			let ty_span = ty.span();
+			let mut ty = ty.clone();
+			// With `for &mut name;`, an arm written as `match &Type` matches
+			// `&mut Type`, but only gets a shared reference.
+			let shared = match (&mode.borrow, &ty) {
+				(Some((_, Some(_))), syn::Type::Reference(reference))
+					if reference.mutability.is_none() =>
+				{
+					let lifetime = reference.lifetime.clone();
+					ty = (*reference.elem).clone();
+					Some(lifetime)
+				}
+				_ => None,
+			};
+			let mut generics = arm.generics.clone();
+			if let syn::Type::Infer(_) = ty {
+				let ident = fresh_ident(quote! { #generics #return_type #(#body)* }, "__Spez");
+				generics.params.push(syn::parse_quote! { #ident });
+				ty = syn::parse_quote! { #ident };
+			}
+			if generics.params.is_empty() {
+				if let Some(where_clause) = &mut generics.where_clause {
+					make_non_trivial(where_clause);
+				}
+			}
+			if let Some((and_token, mutability)) = &mode.borrow {
+				let lifetime = shared.clone().flatten();
+				ty = syn::parse_quote! { #and_token #lifetime #mutability #ty };
+			} else {
+				remove_maybe_sized(&ty, &mut generics);
 			}
-			impl #generics #name for #refs Match<#ty> #where_clause {
-				type Return = #return_type;
-				fn spez(&self) -> Self::Return {
-					#param_def
-					#body
+			// The lifetimes captured by an opaque return type, or used in the
+			// return type through `Self` or elision, must be named.
+			if is_opaque || returns_self || returns_elided {
+				let context = quote! { #generics #ty #return_type #(#body)* };
+				name_elided_lifetimes(&mut ty, &mut generics, context);
+			}
+			let where_clause = &generics.where_clause;
+			let return_type = match &arm.return_type {
+				Some(return_type) if returns_self || returns_elided => {
+					let mut return_type = return_type.clone();
+					if returns_elided {
+						if let [lifetime] = &lifetimes(&ty)[..] {
+							fill_elided_lifetimes(&mut return_type, Some(lifetime));
+						}
+					}
+					let mut self_ty = ty.clone();
+					if let (Some(_), syn::Type::Reference(reference)) = (&shared, &mut self_ty) {
+						reference.mutability = None;
+					}
+					replace_self(&mut return_type, &self_ty);
+					quote! { #return_type }
+				}
+				_ => return_type.clone(),
+			};
+			let arm_param = arm_param
+				// A pattern given for this arm is always used, so a mismatch with
+				// the type is reported.
+				.filter(|param| arm.binding.is_some() || pat_fits(param, &ty))
+				.filter(|param| {
+					!(returns_input || arm.use_trait.is_some()) || pat_to_expr(param).is_some()
+				})
+				.unwrap_or(&hidden_param);
+			let body = match (&arm.use_trait, returns_input) {
+				// The method gets the input the same way as the body of an arm
+				// would, so it takes `&self` when the input is borrowed.
+				(Some(path), _) => {
+					let input = pat_to_expr(arm_param).unwrap();
+					let ty = &arm.tys[0];
+					quote_spanned! {path.span()=> <#ty as #path>::spez(#input) }
+				}
+				(None, true) => pat_to_expr(arm_param).unwrap(),
+				(None, false) => quote! { #(#body)* },
+			};
+			// A borrowed input is only taken out if the body uses it, as there
+			// is nothing to drop. An input taken by value is still moved out, so
+			// it gets dropped at the end of the arm.
+			let warn_unused = warns_unused(arm.attrs.iter().chain(&arm.body_attrs));
+			let is_borrowed = mode.borrow.is_some() || matches!(ty, syn::Type::Reference(_));
+			let takes_input = !is_borrowed
+				|| warn_unused
+				|| arm.guard.is_some()
+				|| mode.is_async
+				|| mode.is_deferred
+				|| returns_input
+				|| arm.use_trait.is_some()
+				|| mentions(arm_param, quote! { #prelude #body });
+			// The guard still gets the mutable reference, so it can pass it on
+			// to the remaining arms.
+			let reborrow = match arm_param {
+				_ if !takes_input => quote! {},
+				syn::Pat::Ident(syn::PatIdent { ident, .. }) if shared.is_some() => quote! {
+					let #ident: &_ = #ident;
+					let _ = #ident;
+				},
+				_ => quote! {},
+			};
+			let body = quote! { #reborrow #prelude #body };
+			// Not every arm uses the input, so unused variable warnings are
+			// suppressed, unless the arm asks for them.
+			let param_def = match arm_param {
+				_ if !takes_input => quote! {},
+				// The binding is mutable in every arm, even without `mut`, so
+				// `unused_mut` would only be noise for the arms that don't
+				// mutate it.
+				syn::Pat::Ident(syn::PatIdent {
+					ident,
+					by_ref: None,
+					subpat: None,
+					..
+				}) => {
+					// Not `#[allow]`, which fails with `#![forbid(unused_variables)]`.
+					let suppress = (!warn_unused).then(|| quote! { let _ = #ident; });
+					// Warnings about the binding are only reported if all of it
+					// comes from the input. Otherwise, it's left as coming from
+					// the macro, so lints like `clippy::unnecessary_cast` don't
+					// fire for bodies shared by multiple types.
+					let mut_token = match warn_unused {
+						true => Token![mut](ident.span()),
+						false => Token![mut](Span::call_site()),
+					};
+					quote! {
+						#[allow(unused_mut)]
+						let #mut_token #ident = #take;
+						#suppress
+					}
+				}
+				pat => {
+					let allow = (!warn_unused).then(|| quote! { #[allow(unused_variables)] });
+					quote! {
+						#allow
+						let #pat = #take;
+					}
+				}
+			};
+			// An `async` or deferred body takes the input along, even if it
+			// doesn't use it, so it lives until the future completes or the
+			// closure is called.
+			let keep_input = match arm_param {
+				syn::Pat::Ident(syn::PatIdent { ident, .. })
+					if mode.is_async || mode.is_deferred =>
+				{
+					quote! { let _ = &#ident; }
+				}
+				_ => quote! {},
+			};
+			let guard = arm.guard.as_ref().map(|guard| {
+				// Fall through by running the remaining arms on the input.
+				let input = match pat_to_expr(arm_param) {
+					Some(input) => input,
+					None => {
+						return syn::Error::new_spanned(
+							arm_param,
+							"an arm with a guard requires the input to be bound to names",
+						)
+						.to_compile_error()
+					}
+				};
+				// The arms after this one are at the layers below it, so the
+				// same impls are used, rather than expanding those arms again.
+				let fallthrough = receiver(&input, depth - 1);
+				let fallthrough = quote! { #fallthrough.spez(#captures_arg) };
+				let fallthrough = if mode.is_async {
+					quote! { #fallthrough.await }
+				} else if mode.is_deferred {
+					quote! { #fallthrough() }
+				} else {
+					fallthrough
+				};
+				// If the remaining arms return a different type, the error
+				// should point at the guard rather than at the whole macro.
+				let value = syn::Ident::new(
+					"__spez_fallthrough",
+					Span::mixed_site().located_at(guard.span()),
+				);
+				// For a type that none of the remaining arms match, the
+				// fallthrough diverges. That only results in an error if this
+				// impl gets used, so there is nothing to warn about.
+				quote! {
+					if !(#guard) {
+						#[allow(unreachable_code, clippy::diverging_sub_expression)]
+						let #value = #fallthrough;
+						#[allow(unreachable_code)]
+						return #value;
+					}
+				}
+			});
+			let self_ty = layer(depth);
+			let self_ty = quote! { #self_ty<#ty> };
+			// Errors about the return type should point at the arm.
+			let return_span = match &arm.return_type {
+				Some(return_type) => return_type.span(),
+				None => ty_span,
+			};
+			let method_return_type = if is_never {
+				quote_spanned! { return_span=> ! }
+			} else if is_opaque {
+				let args = generics.params.iter().map(generic_arg);
+				opaque(quote! { use<#(#args),*> })
+			} else {
+				quote_spanned! { return_span=> Self::Return }
+			};
+			let trait_args = match is_opaque {
+				true => trait_args.clone(),
+				false => quote! {},
+			};
+			let assoc_type = match assoc_type {
+				true if mode.with_index => quote! { type Return = (#return_type, usize); },
+				true => quote! { type Return = #return_type; },
+				false => quote! {},
+			};
+			let body = if mode.with_index && !is_never {
+				// The body is put in a closure (or async block), so a `return`
+				// in the body doesn't skip the index.
+				let index = proc_macro2::Literal::usize_unsuffixed(arm.index);
+				match (mode.is_async, mode.is_deferred) {
+					(true, _) => quote! {
+						async move {
+							#keep_input
+							#guard
+							(async move { #body }.await, #index)
+						}
+					},
+					(false, true) => quote! {
+						move || {
+							#keep_input
+							#guard
+							#[allow(clippy::redundant_closure_call)]
+							((move || { #body })(), #index)
+						}
+					},
+					(false, false) => {
+						let value = syn::Ident::new("__spez_value", Span::mixed_site());
+						quote! {
+							#guard
+							#[allow(clippy::redundant_closure_call)]
+							let #value = (move || {
+								#body
+							})();
+							(#value, #index)
+						}
+					}
+				}
+			} else {
+				match (mode.is_async, mode.is_deferred) {
+					(true, _) => quote! {
+						async move {
+							#keep_input
+							#guard
+							#body
+						}
+					},
+					(false, true) => quote! {
+						move || {
+							#keep_input
+							#guard
+							#body
+						}
+					},
+					(false, false) => quote! {
+						#guard
+						#body
+					},
+				}
+			};
+			let track_caller = (mode.track_caller && !has_fn_attr("track_caller"))
+				.then(|| quote! { #[track_caller] });
+			let inline = (!has_fn_attr("inline")).then(|| quote! { #[inline] });
+			// Without `#[inline]`, a method of a non-generic impl is only
+			// compiled into one codegen unit, and can't be inlined into the
+			// others without LTO. With it, the call (and the `deref`s leading to
+			// it) can always collapse into the arm body. It is only a hint, not
+			// `#[inline(always)]`, as an arm body can be arbitrarily large.
+			traits.extend(quote! {
+				#(#attrs)*
+				impl #generics #name #trait_args for #self_ty #where_clause {
+					#assoc_type
+					#inline
+					#track_caller
+					#(#fn_attrs)*
+					fn spez(&self #captures_param) -> #method_return_type {
+						#(#body_attrs)*
+						#unreachable
+						#captures_def
+						#param_def
+						#body
+					}
+				}
+			});
+		}
+	}
+
+	let match_struct = layer(0);
+	let mut layers = quote! {
+		struct #match_struct<T>(::core::cell::Cell<::core::option::Option<T>>);
+	};
+	for depth in 1..=n_arms {
+		let inner = layer(depth - 1);
+		let outer = layer(depth);
+		layers.extend(quote! {
+			struct #outer<T>(#inner<T>);
+			impl<T> ::core::ops::Deref for #outer<T> {
+				type Target = #inner<T>;
+				#[inline]
+				fn deref(&self) -> &#inner<T> {
+					&self.0
 				}
 			}
 		});
 	}
 
-	let expr = args.expr;
-	let refs = refs(n_arms, is_mutable);
+	// If no arm matches, this fails at compile time, after type checking.
+	let no_match = mode.ident("NoMatch");
+	traits.extend(quote! {
+		trait #no_match {
+			const ERROR: () = ::core::panic!("no arm of spez! matches the type of the input");
+			fn spez(&self #captures_param) -> !;
+		}
+		impl<T> #no_match for #match_struct<T> {
+			#[inline]
+			fn spez(&self #captures_param) -> ! {
+				let () = <Self as #no_match>::ERROR;
+				::core::unreachable!()
+			}
+		}
+	});
 
+	let result = match input {
+		Input::One(expr) => {
+			let receiver = receiver(&expr, n_arms);
+			quote! { #receiver.spez(#captures_arg) }
+		}
+		// The arms are generated once, and selected for each input on its own.
+		Input::Each(exprs) => {
+			let receivers = exprs.iter().map(|expr| receiver(expr, n_arms));
+			quote! { [#(#receivers.spez(#captures_arg)),*] }
+		}
+	};
 	quote! {
 		{
-			struct Match<T>(core::cell::Cell<Option<T>>);
+			#layers
 			#traits
-			(#refs Match(core::cell::Cell::new(Some(#expr)))).spez()
+			#result
 		}
 	}
 }
+
+/// The input of [`expand`].
+enum Input {
+	/// A single expression, for which the expansion evaluates to the value of
+	/// the selected arm.
+	One(TokenStream2),
+	/// Several expressions, for which the expansion evaluates to an array with
+	/// the value of the arm that is selected for each of them.
+	Each(Vec<TokenStream2>),
+}
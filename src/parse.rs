@@ -1,105 +1,952 @@
+use proc_macro2::Delimiter;
+use proc_macro2::Spacing;
+use proc_macro2::Span;
+use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::TokenTree;
+use quote::quote;
 use std::iter::once;
-use std::iter::FromIterator;
-use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit_mut;
+use syn::visit_mut::VisitMut;
 use syn::Token;
 
-#[cfg_attr(debug_assertions, derive(Debug))]
+/// The `&` or `&mut` of `for &name;`.
+pub type Borrow = (Token![&], Option<Token![mut]>);
+
+/// The name, `&`, `in` and expression of the first line.
+type ForInput = (
+	Option<syn::Pat>,
+	Option<Borrow>,
+	Option<Token![in]>,
+	syn::Expr,
+);
+
+#[cfg_attr(test, derive(Debug))]
 pub struct Args {
-	pub for_token: Token![for],
-	pub param: Option<syn::Ident>,
-	pub at_token: Option<Token![=]>,
+	pub prefix: Option<syn::Ident>,
+	pub async_token: Option<Token![async]>,
+	pub param: Option<syn::Pat>,
+	pub borrow: Option<Borrow>,
+	/// The `in` of `for name in [..];`, in which case `expr` is an array of
+	/// inputs that are each matched on their own.
+	pub each: Option<Token![in]>,
 	pub expr: syn::Expr,
-	pub semicolon_token: Token![;],
+	pub return_type: Option<syn::Type>,
+	pub captures: Vec<(syn::Ident, syn::Type)>,
+	pub prelude: Vec<syn::Stmt>,
+	/// The `type` aliases after the prelude, which can be used in all arms.
+	pub aliases: Vec<syn::ItemType>,
 	pub arms: Vec<Arm>,
+	/// Where each arm starts, in the order they are written, for
+	/// `SPEZ_MAX_ARMS`. Arms added by the macro itself are not included.
+	pub arm_spans: Vec<Span>,
+	/// The types of `#[spez(exhaustive(..))]`, which must each match an arm.
+	pub exhaustive: Vec<syn::Type>,
+	/// The `track_caller` of `#[spez(track_caller)]`.
+	pub track_caller: Option<syn::Path>,
 }
 
-#[cfg_attr(debug_assertions, derive(Debug))]
+#[cfg_attr(test, derive(Debug))]
 pub struct Arm {
-	pub match_token: Token![match],
+	pub attrs: Vec<syn::Attribute>,
+	/// The `else` or `default` keyword of a fallback arm.
+	pub fallback: Option<syn::Ident>,
 	pub generics: syn::Generics,
-	pub ty: syn::Type,
-	pub arrow_token: Option<Token![->]>,
+	pub tys: Vec<syn::Type>,
+	/// The name or pattern of `as`, for the input in this arm.
+	pub binding: Option<syn::Pat>,
+	/// The bound of `where !Bound`, which the type must not satisfy.
+	pub not_bound: Option<syn::TraitBound>,
+	pub guard: Option<syn::Expr>,
 	pub return_type: Option<syn::Type>,
+	pub body_attrs: Vec<syn::Attribute>,
 	pub body: syn::Block,
+	/// The trait of `use trait Trait;`, whose `spez` method is the body.
+	pub use_trait: Option<syn::Path>,
+	/// The number of `match(priority = N)`.
+	pub priority: Option<u32>,
+	/// The position of the arm as written, before the arms are reordered.
+	pub index: usize,
 }
 
 impl syn::parse::Parse for Args {
 	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-		let for_token = input.parse()?;
-		let param;
-		let at_token;
-		let expr;
-		if input.peek2(Token![=]) {
-			param = Some(input.parse()?);
-			at_token = Some(input.parse()?);
-			expr = input.parse()?;
-		} else if input.peek(syn::Ident) && input.peek2(Token![;]) {
-			let ident: syn::Ident = input.parse()?;
-			param = Some(ident.clone());
-			at_token = None;
-			expr = ident_to_expr(ident);
+		let mut prefix = None;
+		let mut require_default = false;
+		let mut exhaustive = Vec::new();
+		let mut panic_default = None;
+		let mut track_caller = None;
+		let mut static_only = false;
+		for attr in input.call(syn::Attribute::parse_outer)? {
+			if !attr.path().is_ident("spez") {
+				return Err(syn::Error::new_spanned(
+					attr,
+					"only `#[spez(..)]` attributes can be used before `for`",
+				));
+			}
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("prefix") {
+					let lit: syn::LitStr = meta.value()?.parse()?;
+					prefix = Some(lit.parse()?);
+					Ok(())
+				} else if meta.path.is_ident("require_default") {
+					require_default = true;
+					Ok(())
+				} else if meta.path.is_ident("panic_default") {
+					panic_default = Some(meta.path.span());
+					Ok(())
+				} else if meta.path.is_ident("static_only") {
+					static_only = true;
+					Ok(())
+				} else if meta.path.is_ident("track_caller") {
+					track_caller = Some(meta.path);
+					Ok(())
+				} else if meta.path.is_ident("exhaustive") {
+					let content;
+					syn::parenthesized!(content in meta.input);
+					let tys = content
+						.parse_terminated(<syn::Type as syn::parse::Parse>::parse, Token![,])?;
+					exhaustive.extend(tys);
+					Ok(())
+				} else {
+					Err(meta.error("unknown spez option"))
+				}
+			})?;
+		}
+		let async_token = input.parse()?;
+		input.parse::<Token![for]>()?;
+		let ((param, borrow, each, expr), return_type) =
+			parse_with_return_type(input, parse_input)?;
+		input.parse::<Token![;]>()?;
+		let captures = if peek_ident(input, "capture") && input.peek2(syn::token::Paren) {
+			input.parse::<syn::Ident>()?;
+			let content;
+			syn::parenthesized!(content in input);
+			let captures = content.parse_terminated(
+				|input| {
+					let ident = input.parse()?;
+					input.parse::<Token![:]>()?;
+					Ok((ident, input.parse()?))
+				},
+				Token![,],
+			)?;
+			input.parse::<Token![;]>()?;
+			captures.into_iter().collect()
+		} else {
+			Vec::new()
+		};
+		let prelude = if peek_ident(input, "prelude") && input.peek2(syn::token::Brace) {
+			input.parse::<syn::Ident>()?;
+			let content;
+			syn::braced!(content in input);
+			content.call(syn::Block::parse_within)?
 		} else {
-			param = None;
-			at_token = None;
-			expr = input.parse()?;
+			Vec::new()
+		};
+		let mut aliases = Vec::new();
+		while input.peek(Token![type]) {
+			aliases.push(input.parse()?);
+		}
+		let mut arms = Vec::new();
+		let mut arm_spans = Vec::new();
+		while !input.is_empty() {
+			arm_spans.push(input.span());
+			if !peek_arm(input) {
+				arms.push(parse_trailing_expr(input, &arms)?);
+				break;
+			}
+			arms.push(input.parse::<Arm>()?);
+			if input.peek(Token![,]) {
+				input.parse::<Token![,]>()?;
+			} else if input.peek(Token![;]) {
+				input.parse::<Token![;]>()?;
+			}
+		}
+		if arms.is_empty() {
+			return Err(syn::Error::new(
+				Span::call_site(),
+				"spez! requires at least one match arm",
+			));
+		}
+		if let Some(arm) = arms.iter().rev().skip(1).find(|arm| arm.fallback.is_some()) {
+			let fallback = arm.fallback.as_ref().unwrap();
+			return Err(syn::Error::new_spanned(
+				fallback,
+				format!("the `{}` arm must be the last arm", fallback),
+			));
+		}
+		if let Some(span) = panic_default {
+			if arms.last().is_some_and(|arm| arm.fallback.is_some()) {
+				return Err(syn::Error::new(
+					span,
+					"`#[spez(panic_default)]` can not be used with a `default` or `else` arm",
+				));
+			}
+			// The `!` coerces to the shared return type, if there is one.
+			let return_type = match &return_type {
+				Some(return_type) => quote! { #return_type },
+				None => quote! { ! },
+			};
+			let ty = syn::Ident::new("__Spez", Span::mixed_site());
+			let mut arm: Arm = syn::parse_quote_spanned! {span=>
+				#[allow(deprecated)]
+				match<#ty> #ty -> #return_type {
+					::core::panic!(
+						"no arm of spez! matches the type `{}`",
+						::core::any::type_name::<#ty>(),
+					)
+				}
+			};
+			arm.fallback = Some(syn::Ident::new("default", span));
+			arms.push(arm);
+		}
+		if require_default && arms.last().is_some_and(|arm| arm.fallback.is_none()) {
+			return Err(syn::Error::new(
+				Span::call_site(),
+				"`#[spez(require_default)]` requires a `default` or `else` arm",
+			));
+		}
+		for (index, arm) in arms.iter_mut().enumerate() {
+			arm.index = index;
+		}
+		lower_not_bounds(&mut arms)?;
+		// Arms with a priority come first, from the lowest number. The sort is
+		// stable, so ties keep the order they are written in.
+		arms.sort_by_key(|arm| (arm.priority.is_none(), arm.priority));
+		let mut concrete_tys = Vec::new();
+		for arm in &arms {
+			let [ty] = &arm.tys[..] else { continue };
+			if !arm.generics.params.is_empty()
+				|| arm.generics.where_clause.is_some()
+				|| arm.guard.is_some()
+				|| arm.attrs.iter().any(|attr| attr.path().is_ident("cfg"))
+				|| matches!(ty, syn::Type::Infer(_))
+			{
+				continue;
+			}
+			let key = quote::ToTokens::to_token_stream(ty).to_string();
+			if concrete_tys.contains(&key) {
+				return Err(syn::Error::new_spanned(
+					ty,
+					"this type is already matched by an earlier arm",
+				));
+			}
+			concrete_tys.push(key);
+		}
+		if let Some(guard) = arms.iter().find_map(|arm| arm.guard.as_ref()) {
+			if static_only {
+				return Err(syn::Error::new_spanned(
+					guard,
+					"`#[spez(static_only)]` does not allow guards, as they are checked at run time",
+				));
+			}
+		}
+		if let Some(guard) = arms.last().and_then(|arm| arm.guard.as_ref()) {
+			return Err(syn::Error::new_spanned(
+				guard,
+				"the last arm can not have a guard, as there is no arm to fall back to",
+			));
 		}
 		Ok(Self {
-			for_token,
+			prefix,
+			async_token,
 			param,
-			at_token,
+			borrow,
+			each,
 			expr,
-			semicolon_token: input.parse()?,
-			arms: {
-				let mut arms = Vec::new();
-				while !input.is_empty() {
-					arms.push(input.parse()?);
-				}
-				arms
-			},
+			return_type,
+			captures,
+			prelude,
+			aliases,
+			arms,
+			arm_spans,
+			exhaustive,
+			track_caller,
 		})
 	}
 }
 
 impl syn::parse::Parse for Arm {
 	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-		let match_token = input.parse()?;
+		let attrs = input.call(syn::Attribute::parse_outer)?;
+		let unsafe_token: Option<Token![unsafe]> = input.parse()?;
+		let fallback = if input.peek(Token![else]) {
+			let else_token = input.parse::<Token![else]>()?;
+			Some(syn::Ident::new("else", else_token.span))
+		} else if peek_default(input) {
+			Some(input.parse()?)
+		} else {
+			None
+		};
+		if let Some(fallback) = fallback {
+			let return_type = parse_return_type(input)?;
+			let (body_attrs, body) = parse_body(input, unsafe_token, return_type.is_some())?;
+			let underscore_token = Token![_](fallback.span());
+			return Ok(Self {
+				attrs,
+				fallback: Some(fallback),
+				generics: syn::Generics::default(),
+				tys: vec![syn::Type::Infer(syn::TypeInfer { underscore_token })],
+				binding: None,
+				not_bound: None,
+				guard: None,
+				return_type,
+				body_attrs,
+				body,
+				use_trait: None,
+				priority: None,
+				index: 0,
+			});
+		}
+		if input.peek(Token![use]) && input.peek2(Token![trait]) {
+			if let Some(unsafe_token) = unsafe_token {
+				return Err(syn::Error::new_spanned(
+					unsafe_token,
+					"`unsafe` can not be used with `use trait`",
+				));
+			}
+			input.parse::<Token![use]>()?;
+			input.parse::<Token![trait]>()?;
+			let path: syn::Path = input.parse()?;
+			let return_type = parse_return_type(input)?;
+			input.parse::<Token![;]>()?;
+			let ty = syn::Ident::new("__Spez", Span::mixed_site());
+			return Ok(Self {
+				attrs,
+				fallback: None,
+				generics: syn::parse_quote! { <#ty: #path> },
+				tys: vec![syn::parse_quote! { #ty }],
+				binding: None,
+				not_bound: None,
+				guard: None,
+				return_type,
+				body_attrs: Vec::new(),
+				body: syn::parse_quote! { {} },
+				use_trait: Some(path),
+				priority: None,
+				index: 0,
+			});
+		}
+		if !input.peek(Token![match]) {
+			return Err(input.error("expected `match`, `use trait` or `else`"));
+		}
+		input.parse::<Token![match]>()?;
+		let priority = parse_priority(input)?;
 		let generics = if input.peek(Token![<]) {
 			input.parse()?
 		} else {
 			syn::Generics::default()
 		};
-		let ty = input.parse()?;
-		let where_clause: Option<syn::WhereClause> = input.parse()?;
-		let (arrow_token, return_type) = if input.peek(Token![->]) {
-			(Some(input.parse()?), Some(input.parse()?))
+		if input.is_empty()
+			|| input.peek(syn::token::Brace)
+			|| input.peek(Token![->])
+			|| input.peek(Token![=>])
+			|| peek_arm(input)
+		{
+			return Err(input.error("expected a type after `match`"));
+		}
+		let mut tys = vec![input.parse()?];
+		while let Some(bar) = input.parse::<Option<Token![|]>>()? {
+			if input.peek(syn::token::Brace) || input.peek(Token![->]) || input.peek(Token![where])
+			{
+				return Err(syn::Error::new_spanned(bar, "expected a type after `|`"));
+			}
+			tys.push(input.parse()?);
+		}
+		let binding = if input.peek(Token![as]) {
+			input.parse::<Token![as]>()?;
+			Some(parse_binding(input)?)
 		} else {
-			(None, None)
+			None
 		};
-		let body = input.parse()?;
-		Ok(Self {
-			match_token,
+		if input.peek(Token![where]) && (input.peek2(Token![->]) || input.peek2(syn::token::Brace))
+		{
+			return Err(input.error("expected at least one predicate after `where`"));
+		}
+		let not_bound = if input.peek(Token![where]) && input.peek2(Token![!]) {
+			input.parse::<Token![where]>()?;
+			input.parse::<Token![!]>()?;
+			Some(input.parse()?)
+		} else {
+			None
+		};
+		let where_clause = parse_where_clause(input)?;
+		let guard = if input.peek(Token![if]) {
+			Some(parse_guard(input)?)
+		} else {
+			None
+		};
+		let return_type = parse_return_type(input)?;
+		let (body_attrs, body) = parse_body(input, unsafe_token, return_type.is_some())?;
+		let mut arm = Self {
+			attrs,
+			fallback: None,
 			generics: syn::Generics {
 				where_clause,
 				..generics
 			},
-			ty,
-			arrow_token,
+			tys,
+			binding,
+			not_bound,
+			guard,
 			return_type,
+			body_attrs,
 			body,
-		})
+			use_trait: None,
+			priority,
+			index: 0,
+		};
+		lower_impl_types(&mut arm)?;
+		Ok(arm)
+	}
+}
+
+/// Parses the `(priority = N)` after `match`, if there is one.
+///
+/// Anything else in parentheses is left alone, as it is a tuple type.
+fn parse_priority(input: syn::parse::ParseStream) -> syn::Result<Option<u32>> {
+	let fork = input.fork();
+	let is_priority = (|| {
+		let content;
+		syn::parenthesized!(content in fork);
+		Ok(peek_ident(&content, "priority") && content.peek2(Token![=]))
+	})()
+	.unwrap_or(false);
+	if !is_priority {
+		return Ok(None);
+	}
+	let content;
+	syn::parenthesized!(content in input);
+	content.parse::<syn::Ident>()?;
+	content.parse::<Token![=]>()?;
+	let priority = content.parse::<syn::LitInt>()?.base10_parse()?;
+	if !content.is_empty() {
+		return Err(content.error("expected `)` after the priority"));
+	}
+	Ok(Some(priority))
+}
+
+/// Replaces every `impl Bounds` in the type of an arm by a new generic
+/// parameter with those bounds, so `match impl Debug` works like
+/// `match<T: Debug> T`.
+fn lower_impl_types(arm: &mut Arm) -> syn::Result<()> {
+	struct Visitor {
+		context: TokenStream2,
+		params: Vec<syn::TypeParam>,
+	}
+	impl VisitMut for Visitor {
+		fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+			let syn::Type::ImplTrait(impl_trait) = ty else {
+				return visit_mut::visit_type_mut(self, ty);
+			};
+			let context = &self.context;
+			let params = &self.params;
+			let ident = crate::fresh_ident(quote! { #context #(#params)* }, "__Spez");
+			self.params.push(syn::TypeParam {
+				bounds: impl_trait.bounds.clone(),
+				..ident.clone().into()
+			});
+			*ty = syn::parse_quote! { #ident };
+		}
+		// An `impl Trait` in these is not a type parameter of the impl.
+		fn visit_type_bare_fn_mut(&mut self, _: &mut syn::TypeBareFn) {}
+		fn visit_parenthesized_generic_arguments_mut(
+			&mut self,
+			_: &mut syn::ParenthesizedGenericArguments,
+		) {
+		}
+	}
+	let Arm {
+		generics,
+		tys,
+		guard,
+		return_type,
+		body,
+		..
+	} = &arm;
+	let mut visitor = Visitor {
+		context: quote! { #generics #(#tys)* #guard #return_type #body },
+		params: Vec::new(),
+	};
+	for ty in &mut arm.tys {
+		visitor.visit_type_mut(ty);
+	}
+	if visitor.params.is_empty() {
+		return Ok(());
+	}
+	if arm.tys.len() > 1 {
+		return Err(syn::Error::new(
+			visitor.params[0].bounds.span(),
+			"`impl Trait` can not be used in an arm with multiple types",
+		));
 	}
+	arm.generics
+		.params
+		.extend(visitor.params.into_iter().map(syn::GenericParam::Type));
+	Ok(())
 }
 
-fn ident_to_expr(ident: syn::Ident) -> syn::Expr {
-	syn::Expr::Path(syn::ExprPath {
-		attrs: Vec::new(),
-		qself: None,
-		path: syn::Path {
-			leading_colon: None,
-			segments: Punctuated::from_iter(once(syn::PathSegment {
-				ident,
-				arguments: syn::PathArguments::None,
-			})),
+/// Replaces every arm with a `!Bound` and the catch-all arm after it by the
+/// catch-all arm restricted to `Bound`, followed by the arm without the bound.
+///
+/// As the arms are tried in order, the second one then only gets selected for
+/// types that don't satisfy `Bound`.
+fn lower_not_bounds(arms: &mut Vec<Arm>) -> syn::Result<()> {
+	let mut i = 0;
+	while i < arms.len() {
+		let Some(bound) = arms[i].not_bound.take() else {
+			i += 1;
+			continue;
+		};
+		if !crate::is_catch_all(&arms[i]) || matches!(arms[i].tys[..], [syn::Type::Infer(_)]) {
+			return Err(syn::Error::new_spanned(
+				bound,
+				"a `!` bound can only be used on an arm like `match<T> T`",
+			));
+		}
+		if !arms.get(i + 1).is_some_and(crate::is_catch_all) {
+			return Err(syn::Error::new_spanned(
+				bound,
+				"an arm with a `!` bound must be followed by an arm that matches any type, \
+				 like `else`",
+			));
+		}
+		let mut next = arms.remove(i + 1);
+		if let [syn::Type::Infer(_)] = next.tys[..] {
+			next.generics = arms[i].generics.clone();
+			next.tys = arms[i].tys.clone();
+		}
+		let ty = &next.tys[0];
+		next.generics
+			.make_where_clause()
+			.predicates
+			.push(syn::parse_quote! { #ty: #bound });
+		next.fallback = None;
+		// The catch-all arm is tried together with the arm with the bound.
+		next.priority = arms[i].priority;
+		arms.insert(i, next);
+		i += 2;
+	}
+	Ok(())
+}
+
+/// Checks whether the next tokens start an arm, rather than a trailing
+/// expression.
+fn peek_arm(input: syn::parse::ParseStream) -> bool {
+	let fork = input.fork();
+	if fork
+		.call(syn::Attribute::parse_outer)
+		.is_ok_and(|attrs| !attrs.is_empty())
+	{
+		return true;
+	}
+	fork.parse::<Option<Token![unsafe]>>().unwrap();
+	fork.peek(Token![match])
+		|| fork.peek(Token![else])
+		|| (fork.peek(Token![use]) && fork.peek2(Token![trait]))
+		|| peek_default(&fork)
+}
+
+/// Checks whether the next tokens start a `default` arm, rather than an
+/// expression using a variable named `default`.
+fn peek_default(input: syn::parse::ParseStream) -> bool {
+	peek_ident(input, "default")
+		&& (input.peek2(syn::token::Brace) || input.peek2(Token![->]) || input.peek2(Token![=>]))
+}
+
+/// Parses an expression after the last arm, as an `else` arm that evaluates
+/// to it.
+fn parse_trailing_expr(input: syn::parse::ParseStream, arms: &[Arm]) -> syn::Result<Arm> {
+	let expr: syn::Expr = input.parse()?;
+	if input.peek(Token![,]) {
+		input.parse::<Token![,]>()?;
+	} else if input.peek(Token![;]) {
+		input.parse::<Token![;]>()?;
+	}
+	if !input.is_empty() && peek_arm(input) {
+		return Err(syn::Error::new_spanned(
+			expr,
+			"a trailing expression must come after the last arm",
+		));
+	} else if !input.is_empty() {
+		return Err(syn::Error::new_spanned(
+			expr,
+			"expected `match`, `use trait` or `else`",
+		));
+	}
+	if let Some(fallback) = arms.last().and_then(|arm| arm.fallback.as_ref()) {
+		return Err(syn::Error::new_spanned(
+			expr,
+			format!(
+				"a trailing expression can not be used after the `{}` arm",
+				fallback
+			),
+		));
+	}
+	Ok(syn::parse_quote_spanned! {expr.span()=> else => #expr })
+}
+
+/// Parses the name or pattern after `as` in an arm.
+///
+/// A name followed by braces is only parsed as a struct pattern if the braces
+/// are not the body of the arm.
+fn parse_binding(input: syn::parse::ParseStream) -> syn::Result<syn::Pat> {
+	let fork = input.fork();
+	let is_name = fork.parse::<syn::Ident>().is_ok()
+		&& !fork.peek(syn::token::Paren)
+		&& !fork.peek(Token![::])
+		&& !is_struct_pattern_fields(&fork);
+	if is_name {
+		let ident: syn::Ident = input.parse()?;
+		return Ok(syn::parse_quote! { #ident });
+	}
+	let pat = syn::Pat::parse_single(input)?;
+	if let Some(refutable) = refutable(&pat) {
+		return Err(syn::Error::new_spanned(
+			refutable,
+			"the pattern after `as` must match any value of the type",
+		));
+	}
+	Ok(pat)
+}
+
+/// Checks whether the next tokens are braces with the fields of a struct
+/// pattern, rather than the body of the arm.
+fn is_struct_pattern_fields(input: syn::parse::ParseStream) -> bool {
+	if !input.peek(syn::token::Brace) {
+		return false;
+	}
+	input.parse::<TokenTree>().unwrap();
+	input.peek(syn::token::Brace)
+		|| input.peek(Token![->])
+		|| input.peek(Token![=>])
+		|| input.peek(Token![if])
+		|| input.peek(Token![where])
+}
+
+/// Returns a part of `pat` that only matches some values, like a literal.
+///
+/// Paths to enum variants can't be told apart from structs here, so those are
+/// left to the compiler.
+fn refutable(pat: &syn::Pat) -> Option<&syn::Pat> {
+	match pat {
+		syn::Pat::Lit(_) | syn::Pat::Range(_) | syn::Pat::Or(_) => Some(pat),
+		syn::Pat::Ident(pat) => pat.subpat.as_ref().and_then(|(_, pat)| refutable(pat)),
+		syn::Pat::Paren(pat) => refutable(&pat.pat),
+		syn::Pat::Reference(pat) => refutable(&pat.pat),
+		syn::Pat::Tuple(pat) => pat.elems.iter().find_map(refutable),
+		syn::Pat::TupleStruct(pat) => pat.elems.iter().find_map(refutable),
+		syn::Pat::Slice(pat) => pat.elems.iter().find_map(refutable),
+		syn::Pat::Struct(pat) => pat.fields.iter().find_map(|field| refutable(&field.pat)),
+		_ => None,
+	}
+}
+
+/// Checks whether the next token is the identifier `name`.
+fn peek_ident(input: syn::parse::ParseStream, name: &str) -> bool {
+	matches!(input.fork().parse::<syn::Ident>(), Ok(ident) if ident == name)
+}
+
+/// Parses the `where` clause of an arm, if there is one.
+///
+/// Unlike `syn`, this accepts a predicate without bounds, like `[(); N - 1]:`,
+/// right before the guard, return type or body.
+fn parse_where_clause(input: syn::parse::ParseStream) -> syn::Result<Option<syn::WhereClause>> {
+	let Some(where_token) = input.parse::<Option<Token![where]>>()? else {
+		return Ok(None);
+	};
+	let end = |input: syn::parse::ParseStream| {
+		input.is_empty()
+			|| input.peek(Token![if])
+			|| input.peek(Token![->])
+			|| input.peek(Token![=>])
+			|| input.peek(syn::token::Brace)
+	};
+	let mut predicates = syn::punctuated::Punctuated::new();
+	while !end(input) {
+		if input.peek(syn::Lifetime) {
+			predicates.push_value(input.parse()?);
+		} else {
+			let lifetimes = input.parse()?;
+			let bounded_ty = input.parse()?;
+			let colon_token = input.parse()?;
+			let mut bounds = syn::punctuated::Punctuated::new();
+			while !end(input) && !input.peek(Token![,]) {
+				bounds.push_value(input.parse()?);
+				match input.parse::<Option<Token![+]>>()? {
+					Some(plus) => bounds.push_punct(plus),
+					None => break,
+				}
+			}
+			predicates.push_value(syn::WherePredicate::Type(syn::PredicateType {
+				lifetimes,
+				bounded_ty,
+				colon_token,
+				bounds,
+			}));
+		}
+		match input.parse::<Option<Token![,]>>()? {
+			Some(comma) => predicates.push_punct(comma),
+			None => break,
+		}
+	}
+	Ok(Some(syn::WhereClause {
+		where_token,
+		predicates,
+	}))
+}
+
+fn parse_return_type(input: syn::parse::ParseStream) -> syn::Result<Option<syn::Type>> {
+	if input.peek(Token![->]) {
+		let arrow = input.parse::<Token![->]>()?;
+		if input.is_empty()
+			|| input.peek(syn::token::Brace)
+			|| input.peek(Token![=>])
+			|| input.peek(Token![;])
+			|| input.peek(Token![,])
+			|| peek_arm(input)
+		{
+			return Err(syn::Error::new_spanned(
+				arrow,
+				"expected a return type after `->`",
+			));
+		}
+		Ok(Some(input.parse()?))
+	} else {
+		Ok(None)
+	}
+}
+
+/// Parses the tokens up to the next `;` with `parser`, if necessary after
+/// splitting off a trailing `-> Type`.
+///
+/// Something like `f as fn() -> i32` is parsed as a whole, without splitting.
+fn parse_with_return_type<T>(
+	input: syn::parse::ParseStream,
+	parser: fn(syn::parse::ParseStream) -> syn::Result<T>,
+) -> syn::Result<(T, Option<syn::Type>)> {
+	let tokens = input.step(|cursor| {
+		let mut rest = *cursor;
+		let mut tokens = Vec::new();
+		while let Some((tt, next)) = rest.token_tree() {
+			if matches!(&tt, TokenTree::Punct(punct) if punct.as_char() == ';') {
+				break;
+			}
+			tokens.push(tt);
+			rest = next;
+		}
+		Ok((tokens, rest))
+	})?;
+	let error = match syn::parse::Parser::parse2(parser, tokens.iter().cloned().collect()) {
+		Ok(value) => return Ok((value, None)),
+		Err(error) => error,
+	};
+	// The return type can contain `->` too, like `Option<fn() -> i32>`, so the
+	// first split where both sides parse is used.
+	let arrows = tokens
+		.windows(2)
+		.enumerate()
+		.filter(|(_, pair)| match pair {
+			[TokenTree::Punct(a), TokenTree::Punct(b)] => {
+				a.as_char() == '-' && a.spacing() == Spacing::Joint && b.as_char() == '>'
+			}
+			_ => false,
+		});
+	for (arrow, _) in arrows {
+		let ty = tokens[arrow + 2..].iter().cloned().collect();
+		if let Ok(ty) = syn::parse2(ty) {
+			let tokens = tokens[..arrow].iter().cloned().collect();
+			if let Ok(value) = syn::parse::Parser::parse2(parser, tokens) {
+				return Ok((value, Some(ty)));
+			}
+		}
+	}
+	// Without the `;`, the first arm ends up in the input. An input like
+	// `if c { 1 } else { 2 }` also contains these keywords, but parses above.
+	if let Some(arm) = tokens
+		.iter()
+		.skip(1)
+		.find(|tt| matches!(tt, TokenTree::Ident(ident) if ident == "match" || ident == "else"))
+	{
+		return Err(syn::Error::new_spanned(
+			arm,
+			"expected `;` before the first arm",
+		));
+	}
+	Err(error)
+}
+
+/// Parses the input after `for`: an expression, optionally preceded by a
+/// pattern and `=`, or a pattern followed by `in` and a list of expressions.
+fn parse_input(input: syn::parse::ParseStream) -> syn::Result<ForInput> {
+	let fork = input.fork();
+	if syn::Pat::parse_single(&fork).is_ok() && fork.peek(Token![in]) {
+		let param = syn::Pat::parse_single(input)?;
+		let in_token = input.parse()?;
+		let expr = input.parse()?;
+		if !matches!(expr, syn::Expr::Array(_)) {
+			return Err(syn::Error::new_spanned(
+				expr,
+				"expected a list of expressions in `[..]` after `in`",
+			));
+		}
+		return Ok((Some(param), None, Some(in_token), expr));
+	}
+	if input.peek(Token![ref]) {
+		// `for ref name = expr;` is the same as `for &name;`, but for any expression.
+		let ref_token = input.parse::<Token![ref]>()?;
+		let mutability = input.parse::<Option<Token![mut]>>()?;
+		let ident: syn::Ident = input.parse()?;
+		let expr: syn::Expr = match input.parse::<Option<Token![=]>>()? {
+			Some(_) => input.parse()?,
+			None => syn::parse_quote! { #ident },
+		};
+		let and_token = Token![&](ref_token.span);
+		let param = syn::parse_quote! { #ident };
+		let expr = syn::parse_quote! { #and_token #mutability (#expr) };
+		return Ok((Some(param), Some((and_token, mutability)), None, expr));
+	}
+	if input.peek2(Token![=]) || input.peek(Token![mut]) {
+		let param = syn::Pat::parse_single(input)?;
+		if let syn::Pat::Ident(syn::PatIdent {
+			by_ref: None,
+			mutability: Some(mut_token),
+			..
+		}) = &param
+		{
+			return Err(syn::Error::new_spanned(
+				mut_token,
+				"the input is already mutable in every arm, so `mut` is not needed",
+			));
+		}
+		input.parse::<Token![=]>()?;
+		return Ok((Some(param), None, None, input.parse()?));
+	}
+	let expr = input.parse()?;
+	if let syn::Expr::Reference(reference) = &expr {
+		// `for &name;` borrows the input and adds the `&` to all arms.
+		if let Some(param @ syn::Pat::Ident(_)) = expr_to_pat(&reference.expr) {
+			let borrow = (reference.and_token, reference.mutability);
+			return Ok((Some(param), Some(borrow), None, expr));
+		}
+	}
+	Ok((expr_to_pat(&expr), None, None, expr))
+}
+
+/// Parses `if` followed by an expression, up to the `->`, `=>` or block that
+/// follows it.
+fn parse_guard(input: syn::parse::ParseStream) -> syn::Result<syn::Expr> {
+	let if_token = input.parse::<Token![if]>()?;
+	let tokens = input.step(|cursor| {
+		let mut rest = *cursor;
+		let mut tokens = TokenStream2::new();
+		while let Some((tt, next)) = rest.token_tree() {
+			match &tt {
+				TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => break,
+				TokenTree::Punct(punct)
+					if matches!(punct.as_char(), '-' | '=')
+						&& punct.spacing() == Spacing::Joint
+						&& matches!(next.punct(), Some((p, _)) if p.as_char() == '>') =>
+				{
+					break
+				}
+				_ => {}
+			}
+			tokens.extend(once(tt));
+			rest = next;
+		}
+		Ok((tokens, rest))
+	})?;
+	if tokens.is_empty() {
+		return Err(syn::Error::new_spanned(
+			if_token,
+			"expected an expression after `if`",
+		));
+	}
+	syn::parse2(tokens)
+}
+
+/// Parses either a block, or `=>` followed by an expression.
+///
+/// Inner attributes of the block are returned separately. For an `unsafe`
+/// arm, the body is wrapped in an `unsafe` block.
+fn parse_body(
+	input: syn::parse::ParseStream,
+	unsafe_token: Option<Token![unsafe]>,
+	has_return_type: bool,
+) -> syn::Result<(Vec<syn::Attribute>, syn::Block)> {
+	let (attrs, block) = if input.peek(Token![=>]) {
+		let arrow = input.parse::<Token![=>]>()?;
+		if input.is_empty() || input.peek(Token![;]) || input.peek(Token![,]) || peek_arm(input) {
+			return Err(syn::Error::new_spanned(
+				arrow,
+				"expected an expression after `=>`",
+			));
+		}
+		let expr: syn::Expr = input.parse()?;
+		(
+			Vec::new(),
+			syn::Block {
+				brace_token: syn::token::Brace(expr.span()),
+				stmts: vec![syn::Stmt::Expr(expr, None)],
+			},
+		)
+	} else if !input.peek(syn::token::Brace) && has_return_type {
+		return Err(input.error("expected the body of the arm, as `{ .. }` or `=> expression`"));
+	} else if !input.peek(syn::token::Brace) {
+		return Err(input.error("expected `{`, `=>`, or `->` followed by the return type"));
+	} else {
+		let content;
+		let brace_token = syn::braced!(content in input);
+		let attrs = content.call(syn::Attribute::parse_inner)?;
+		let stmts = content.call(syn::Block::parse_within)?;
+		(attrs, syn::Block { brace_token, stmts })
+	};
+	let block = match unsafe_token {
+		Some(unsafe_token) => syn::Block {
+			brace_token: block.brace_token,
+			stmts: vec![syn::Stmt::Expr(
+				syn::Expr::Unsafe(syn::ExprUnsafe {
+					attrs: Vec::new(),
+					unsafe_token,
+					block,
+				}),
+				None,
+			)],
 		},
-	})
+		None => block,
+	};
+	Ok((attrs, block))
+}
+
+/// Converts an identifier, or a (nested) tuple of identifiers, to a pattern
+/// binding the same names.
+fn expr_to_pat(expr: &syn::Expr) -> Option<syn::Pat> {
+	match expr {
+		syn::Expr::Path(path) if path.attrs.is_empty() && path.qself.is_none() => {
+			Some(syn::Pat::Ident(syn::PatIdent {
+				attrs: Vec::new(),
+				by_ref: None,
+				mutability: None,
+				ident: path.path.get_ident()?.clone(),
+				subpat: None,
+			}))
+		}
+		syn::Expr::Tuple(tuple) if tuple.attrs.is_empty() => Some(syn::Pat::Tuple(syn::PatTuple {
+			attrs: Vec::new(),
+			paren_token: tuple.paren_token,
+			elems: tuple
+				.elems
+				.pairs()
+				.map(|pair| {
+					let (elem, comma) = pair.into_tuple();
+					Some(syn::punctuated::Pair::new(
+						expr_to_pat(elem)?,
+						comma.cloned(),
+					))
+				})
+				.collect::<Option<_>>()?,
+		})),
+		_ => None,
+	}
 }
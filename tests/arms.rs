@@ -0,0 +1,1426 @@
+use spez::spez;
+
+#[test]
+fn wildcard_first_match_wins() {
+	let result = spez! {
+		for 1u8;
+		match u16 -> i32 { 0 }
+		match _ -> i32 { 1 }
+		#[allow(deprecated)]
+		match u8 -> i32 { 2 }
+	};
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn wildcard_after_match() {
+	let result = spez! {
+		for 1u8;
+		match u8 -> i32 { 0 }
+		match _ -> i32 { 1 }
+	};
+	assert_eq!(result, 0);
+}
+
+#[test]
+fn wildcard_does_not_shadow_user_types() {
+	#[allow(non_camel_case_types)]
+	struct __Spez;
+	let result = spez! {
+		for 1u8;
+		match _ -> __Spez { __Spez }
+	};
+	let __Spez = result;
+}
+
+#[test]
+fn else_arm() {
+	let result = spez! {
+		for "x";
+		match u8 -> i32 { 0 }
+		else -> i32 { 1 }
+	};
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn default_arm() {
+	let result = spez! {
+		for "x";
+		match u8 -> i32 { 0 }
+		default -> i32 { 1 }
+	};
+	assert_eq!(result, 1);
+
+	let result = spez! {
+		#[spez(require_default)]
+		for 1u8;
+		match u8 -> i32 { 0 }
+		default -> i32 { 1 }
+	};
+	assert_eq!(result, 0);
+
+	// `default` is still usable as a name.
+	let default = 2;
+	let result = spez! {
+		for default;
+		match i32 -> i32 => default
+		else -> i32 { 0 }
+	};
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn trailing_expression() {
+	macro_rules! f {
+		($e:expr) => {
+			spez! {
+				for x = $e -> i32;
+				match i32 { x }
+				match u8 => 1,
+				-1
+			}
+		};
+	}
+	assert_eq!(f!(5i32), 5);
+	assert_eq!(f!(5u8), 1);
+	assert_eq!(f!("a"), -1);
+
+	let result = spez! {
+		for x = 1u8 -> &'static str;
+		match i8 { "i8" }
+		{
+			let s = ["other"];
+			s[0]
+		}
+	};
+	assert_eq!(result, "other");
+
+	let result = spez! {
+		#[spez(require_default)]
+		for "a" -> i32;
+		match i32 => 1
+		String::new().len() as i32;
+	};
+	assert_eq!(result, 0);
+
+	let () = spez! {
+		for ();
+		match () {}
+		()
+	};
+}
+
+#[test]
+fn exhaustive() {
+	struct A;
+	struct B;
+	#[derive(Clone)]
+	struct C;
+	macro_rules! handle {
+		($e:expr) => {
+			spez! {
+				#[spez(exhaustive(A, B, C, &'static str))]
+				for x = $e;
+				match A -> i32 { 0 }
+				match B | &'static str -> i32 { 1 }
+				match<T: Clone> T -> i32 { 2 }
+				else -> i32 { 3 }
+			}
+		};
+	}
+	assert_eq!(handle!(A), 0);
+	assert_eq!(handle!(B), 1);
+	assert_eq!(handle!(C), 2);
+	assert_eq!(handle!(1.0f64), 2);
+
+	let mut v = 1u8;
+	let result = spez! {
+		#[spez(exhaustive(u8, u16))]
+		for &mut v;
+		match u8 | u16 -> i32 { 0 }
+		else -> i32 { 1 }
+	};
+	assert_eq!(result, 0);
+}
+
+#[test]
+fn panic_default() {
+	let result = spez! {
+		#[spez(panic_default)]
+		for x = 1u8;
+		match u8 -> u8 { x }
+	};
+	assert_eq!(result, 1);
+
+	let caught = std::panic::catch_unwind(|| {
+		spez! {
+			#[spez(panic_default)]
+			for x = "abc" -> i32;
+			match i32 { x }
+		}
+	});
+	let message = caught.unwrap_err().downcast::<String>().unwrap();
+	assert_eq!(*message, "no arm of spez! matches the type `&str`");
+
+	let future = spez! {
+		#[spez(panic_default)]
+		async for x = 2u8 -> u8;
+		match u8 { x }
+	};
+	assert_eq!(block_on(future), 2);
+}
+
+mod describe {
+	pub trait Describe {
+		fn spez(self) -> String;
+	}
+
+	impl Describe for std::time::Duration {
+		fn spez(self) -> String {
+			format!("{} ms", self.as_millis())
+		}
+	}
+
+	impl Describe for u8 {
+		fn spez(self) -> String {
+			String::from("not used")
+		}
+	}
+
+	pub trait DescribeRef {
+		fn spez(&self) -> String;
+	}
+
+	impl DescribeRef for String {
+		fn spez(&self) -> String {
+			format!("string {self}")
+		}
+	}
+}
+
+#[test]
+fn use_trait() {
+	use std::time::Duration;
+	macro_rules! describe {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match u8 -> String { String::from("byte") }
+				use trait describe::Describe -> String;
+				match<T: std::fmt::Debug> T -> String { format!("{x:?}") }
+				else -> String { String::from("?") }
+			}
+		};
+	}
+	assert_eq!(describe!(1u8), "byte");
+	assert_eq!(describe!(Duration::from_millis(5)), "5 ms");
+	assert_eq!(describe!("a"), "\"a\"");
+	assert_eq!(describe!(std::cell::Cell::new(1u8)), "Cell { value: 1 }");
+
+	let s = String::from("x");
+	let result = spez! {
+		for &s;
+		use trait describe::DescribeRef -> String;
+		else -> String { String::new() }
+	};
+	assert_eq!(result, "string x");
+	assert_eq!(s, "x");
+
+	trait Sum {
+		fn spez(self) -> u8;
+	}
+	impl Sum for (u8, u8) {
+		fn spez(self) -> u8 {
+			self.0 + self.1
+		}
+	}
+	let result = spez! {
+		for (a, b) = (1u8, 2u8) -> u8;
+		match (u8, u8) if a > b { 0 }
+		use trait Sum;
+		else { 0 }
+	};
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn track_caller() {
+	use core::panic::Location;
+
+	#[track_caller]
+	fn location<T>(x: T) -> &'static Location<'static> {
+		spez! {
+			#[spez(track_caller)]
+			for x;
+			match u8 if x > 5 -> &'static Location<'static> { Location::caller() }
+			else -> &'static Location<'static> { Location::caller() }
+		}
+	}
+
+	let (line, result) = (line!(), location(1u8));
+	assert_eq!((result.file(), result.line()), (file!(), line));
+
+	#[rustfmt::skip]
+	let (line, result) = (line!(), spez! {
+		#[spez(track_caller)]
+		for 1u8;
+		match u8 if false -> &'static Location<'static> { Location::caller() }
+		else -> &'static Location<'static> { Location::caller() }
+	});
+	assert_eq!((result.file(), result.line()), (file!(), line));
+}
+
+#[test]
+fn multiple_types() {
+	let a = spez! {
+		for 1i64;
+		match i32 | i64 -> u64 { 1 }
+		match _ -> u64 { 2 }
+	};
+	let b = spez! {
+		for x = 7u8;
+		match<T> Vec<T> | [T; 2] -> usize { x.len() }
+		match u8 | u16 -> usize { x as usize }
+	};
+	let c = spez! {
+		for [1, 2];
+		match<T> Vec<T> | [T; 2] -> u8 { 0 }
+		match _ -> u8 { 2 }
+	};
+	assert_eq!((a, b, c), (1, 7, 0));
+}
+
+#[test]
+fn cfg_arms() {
+	let result = spez! {
+		for 1u8;
+		#[cfg(any())]
+		match u8 -> i32 { 0 }
+		#[cfg(all())]
+		match u8 -> i32 { 1 }
+		match _ -> i32 { 2 }
+	};
+	assert_eq!(result, 1);
+	let result = spez! {
+		for 1u8;
+		match u16 -> i32 { 0 }
+		#[cfg(any())]
+		match _ -> i32 { 1 }
+		#[cfg_attr(all(), allow(unused_variables))]
+		match u8 -> i32 {
+			let unused = 2;
+			3
+		}
+	};
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn concrete_where_clause() {
+	trait Special {}
+	impl Special for String {}
+	let result = spez! {
+		for x = String::from("abc");
+		match String where String: Special -> usize { x.len() }
+		match String -> usize { 0 }
+	};
+	assert_eq!(result, 3);
+	let result = spez! {
+		for x = 5u8;
+		match u8 where u8: Special, u8: Copy -> u8 { 0 }
+		match u8 -> u8 { x }
+	};
+	assert_eq!(result, 5);
+}
+
+#[test]
+fn arm_binding() {
+	let result = spez! {
+		for x = String::from("abc");
+		match String as s -> usize { s.len() }
+		match _ -> usize { 0 }
+	};
+	assert_eq!(result, 3);
+	let result = spez! {
+		for x = 3u8;
+		match u8 | u16 as n -> u8 { n as u8 + 1 }
+		match _ -> u8 { 0 }
+	};
+	assert_eq!(result, 4);
+	let result = spez! {
+		for x = 3u8;
+		match u16 as n -> u8 { n as u8 }
+		match u8 -> u8 { x }
+	};
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn arm_patterns() {
+	#[derive(Debug, PartialEq)]
+	struct Point(i32, i32);
+	struct Size {
+		w: u32,
+		h: u32,
+	}
+	let result = spez! {
+		for p = Point(1, 2);
+		match Point as Point(x, y) -> i32 { x + y }
+	};
+	assert_eq!(result, 3);
+	let result = spez! {
+		for s = Size { w: 2, h: 3 };
+		match Size as Size { w, h } -> u32 { w * h }
+	};
+	assert_eq!(result, 6);
+	let result = spez! {
+		for s = Size { w: 2, h: 3 };
+		match Size as Size { w, h: height } if w > 5 -> u32 { w * height }
+		match Size as Size { w, .. } -> u32 { w }
+	};
+	assert_eq!(result, 2);
+	let result = spez! {
+		for p = Point(4, 5);
+		match Point as Point(x, y) if x > y -> Self { Point(0, 0) }
+		match Point as p -> Self { p }
+	};
+	assert_eq!(result, Point(4, 5));
+	let result = spez! {
+		for (1u8, 2u8);
+		match (u8, u8) as (a, mut b) -> u8 { b += 1; a + b }
+		match Point as Point(x, _) -> u8 { x as u8 }
+	};
+	assert_eq!(result, 4);
+}
+
+#[test]
+fn type_aliases() {
+	use std::collections::HashMap;
+	macro_rules! len {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				type M = HashMap<String, Vec<u8>>;
+				type V<T> = Vec<T>;
+				match M if x.is_empty() -> usize { M::new().len() }
+				match M -> usize { x.values().map(V::len).sum() }
+				match V<u8> | V<i8> -> usize { x.len() }
+				else -> usize { 0 }
+			}
+		};
+	}
+	let map = HashMap::from([
+		(String::from("a"), vec![1u8, 2]),
+		(String::from("b"), vec![3]),
+	]);
+	assert_eq!(len!(map), 3);
+	assert_eq!(len!(HashMap::<String, Vec<u8>>::new()), 0);
+	assert_eq!(len!(vec![1i8]), 1);
+	assert_eq!(len!(1u8), 0);
+}
+
+#[test]
+fn expression_bodies() {
+	let result = spez! {
+		for x = 5u8;
+		match u16 -> u8 => 0
+		match u8 | u32 -> u8 => x as u8 * 2
+		match _ -> u8 { 1 }
+	};
+	assert_eq!(result, 10);
+	let result = spez! {
+		for x = 5u8;
+		match u16 -> u8 { 0 }
+		#[allow(unused_parens)]
+		match u8 -> u8 => (x)
+		else -> u8 => 1
+	};
+	assert_eq!(result, 5);
+}
+
+#[test]
+fn separators() {
+	let result = spez! {
+		for x = 5u8;
+		match u16 -> u8 { 0 },
+		match u32 -> u8 { 1 };
+		match u8 -> u8 => x,
+		else -> u8 => 3,
+	};
+	assert_eq!(result, 5);
+}
+
+#[test]
+fn formatting() {
+	use std::fmt::Debug;
+	#[rustfmt::skip]
+	let result = spez! {
+		for x
+			= 5u8 // The input.
+			;
+		#[allow(unused_variables)]
+		#[cfg(all())]
+		match<
+			T: Debug
+				+ Clone,
+			U,
+		>
+		(T, U) // A tuple.
+		where
+			U: Copy,
+			T: PartialEq,
+		-> /* The return type. */ String
+		{
+			String::new()
+		}
+		match
+			u8
+			if x
+				> 10
+			-> u8
+			=> 0,
+		match u8
+			as n
+			->
+			u8
+			/* The body. */ {
+				n
+			}
+	};
+	assert_eq!(result, 5);
+}
+
+#[test]
+fn body_attributes() {
+	let result = spez! {
+		for x = 5u8;
+		/// Documentation for this arm.
+		match u8 -> u8 {
+			#![allow(unused_variables)]
+			//! More documentation.
+			let unused = 1;
+			x
+		}
+		else -> u8 { 0 }
+	};
+	assert_eq!(result, 5);
+}
+
+#[test]
+fn function_attributes() {
+	use core::panic::Location;
+
+	// These apply to the generated method, not to its impl.
+	#[rustfmt::skip]
+	let (line, result) = (line!(), spez! {
+		for 1u8;
+		#[inline(never)]
+		match u16 -> u32 { 0 }
+		#[cold]
+		#[track_caller]
+		match u8 -> u32 { Location::caller().line() }
+	});
+	assert_eq!(result, line);
+}
+
+#[test]
+fn unsafe_bodies() {
+	let bytes: &[u8] = b"abc";
+	let result = spez! {
+		for x = bytes;
+		match<'a> &'a [u8] -> &'a str { unsafe { std::str::from_utf8_unchecked(x) } }
+		else -> &'static str { "" }
+	};
+	assert_eq!(result, "abc");
+
+	let result = spez! {
+		for x = bytes;
+		unsafe match<'a> &'a [u8] -> &'a str { std::str::from_utf8_unchecked(x) }
+		else -> &'static str { "" }
+	};
+	assert_eq!(result, "abc");
+
+	let result = spez! {
+		for x = 1u8;
+		match &[u8] -> usize { 0 }
+		unsafe else -> usize => std::mem::size_of_val(&*std::ptr::addr_of!(x))
+	};
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn guards() {
+	let f = |v: i32| {
+		spez! {
+			for x = v;
+			match i32 if x > 10 -> &'static str { "big" }
+			match i32 if x > 5 -> &'static str { "medium" }
+			match u8 -> &'static str { "u8" }
+			match i32 -> &'static str { "small" }
+		}
+	};
+	assert_eq!(f(20), "big");
+	assert_eq!(f(7), "medium");
+	assert_eq!(f(1), "small");
+}
+
+#[test]
+fn static_only() {
+	let result = spez! {
+		#[spez(static_only)]
+		for x = 5i32;
+		match i32 -> i32 { x + 1 }
+		else -> i32 { 0 }
+	};
+	assert_eq!(result, 6);
+}
+
+#[test]
+fn guard_without_name() {
+	let result = spez! {
+		for String::from("abc");
+		match String if false -> usize { 0 }
+		match String as s -> usize { s.len() }
+	};
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn guard_on_generic_arm() {
+	let result = spez! {
+		for x = 3u8;
+		match<T: Into<u64> + Copy> T if x.into() > 5 -> u64 { 0 }
+		match _ -> u64 { 1 }
+	};
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn many_guards() {
+	// Every guard falls through to the same arms, which are only generated
+	// once, so the expansion grows linearly with the number of guards.
+	macro_rules! guards {
+		($x:expr; $($n:literal)*) => {
+			spez::spez_with_index! {
+				for x = $x;
+				$(match i32 if x == $n -> i32 { $n })*
+				match i32 -> i32 { -1 }
+			}
+		};
+	}
+	macro_rules! twenty {
+		($x:expr) => {
+			guards!($x; 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19)
+		};
+	}
+	assert_eq!(twenty!(0), (0, 0));
+	assert_eq!(twenty!(7), (7, 7));
+	assert_eq!(twenty!(19), (19, 19));
+	assert_eq!(twenty!(20), (-1, 20));
+}
+
+#[test]
+fn shared_return_type() {
+	let result = spez! {
+		for x = 1u8 + 1 -> u32;
+		match u8 { x.into() }
+		match u16 => 0
+		else -> &'static str { "" }
+	};
+	assert_eq!(result, 2);
+	let f = |v| {
+		spez! {
+			for x = v -> Option<usize>;
+			match i32 if x < 0 => None
+			match i32 => Some(x as usize)
+		}
+	};
+	assert_eq!(f(-1), None);
+	assert_eq!(f(1), Some(1));
+	let g = spez! {
+		for |a: i32| -> i32 { a + 1 };
+		match<F: Fn(i32) -> i32> F -> i32 { 0 }
+		else -> i32 { 1 }
+	};
+	assert_eq!(g, 0);
+	// The return type can contain `->` itself.
+	fn one() -> i32 {
+		1
+	}
+	let f = one as fn() -> i32;
+	let h = spez! {
+		for g = f -> Option<fn() -> i32>;
+		match fn() -> i32 { Some(g) }
+		else { None }
+	};
+	assert_eq!(h.map(|h| h()), Some(1));
+}
+
+#[test]
+fn shared_return_type_keywords() {
+	// The `else` and `match` in these inputs are not mistaken for arms.
+	let c = true;
+	let result = spez! {
+		for x = if c { 1u8 } else { 2u8 } -> u8;
+		match u8 { x }
+	};
+	assert_eq!(result, 1);
+	let result = spez! {
+		for x = match c { true => 3u8, false => 4u8 } -> u8;
+		match u8 { x }
+	};
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn return_input() {
+	let result = spez! {
+		for x = 1u8 + 1;
+		match u8 -> Self => self
+		else -> u8 { 0 }
+	};
+	assert_eq!(result, 2);
+
+	let result = spez! {
+		for x = 5u16;
+		match u8 | u16 -> Option<Self> { Some(x) }
+		else -> Option<u16> { None }
+	};
+	assert_eq!(result, Some(5));
+
+	let s = String::from("abc");
+	let result = spez! {
+		for x = s.as_str();
+		match &str if x.is_empty() -> Self { "empty" }
+		match &str -> Self => self
+		else -> &'static str { "" }
+	};
+	assert_eq!(result, "abc");
+
+	let result = spez! {
+		for &s;
+		match String -> Self => self
+		else -> Self => self
+	};
+	assert_eq!(result, "abc");
+}
+
+#[test]
+fn impl_trait_return_type() {
+	let iter = spez! {
+		for x = 4;
+		match i32 -> impl Iterator<Item = i32> { 0..x }
+		match &str -> usize { 0 }
+	};
+	assert_eq!(iter.collect::<Vec<_>>(), [0, 1, 2, 3]);
+	let iter = spez! {
+		for x = vec![1u8, 2];
+		match<T: Clone> Vec<T> -> impl Iterator<Item = T> + Clone { x.into_iter() }
+		match _ -> impl Iterator<Item = u8> { core::iter::empty() }
+	};
+	assert_eq!(iter.sum::<u8>(), 3);
+	let s = String::from("a b");
+	let iter = spez! {
+		for x = s.as_str();
+		match<'a> &'a str -> impl Iterator<Item = &'a str> { x.split(' ') }
+		match _ -> u8 { 0 }
+	};
+	assert_eq!(iter.count(), 2);
+}
+
+#[test]
+fn never_return_type() {
+	let result: i32 = spez! {
+		for x = 5;
+		match &str -> ! { panic!("nope") }
+		match i32 -> i32 { x }
+	};
+	assert_eq!(result, 5);
+}
+
+#[test]
+#[should_panic(expected = "nope")]
+fn never_return_type_selected() {
+	spez! {
+		for "a";
+		match &str -> ! { panic!("nope") }
+		match i32 -> i32 { 0 }
+	}
+}
+
+fn block_on<F: core::future::Future>(future: F) -> F::Output {
+	use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+	fn raw_waker() -> RawWaker {
+		RawWaker::new(core::ptr::null(), &VTABLE)
+	}
+	static VTABLE: RawWakerVTable = RawWakerVTable::new(|_| raw_waker(), |_| {}, |_| {}, |_| {});
+	let waker = unsafe { Waker::from_raw(raw_waker()) };
+	let mut context = Context::from_waker(&waker);
+	let mut future = core::pin::pin!(future);
+	loop {
+		if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+			return output;
+		}
+	}
+}
+
+async fn double(x: i32) -> i32 {
+	x * 2
+}
+
+#[test]
+fn async_bodies() {
+	let future = spez! {
+		async for x = 21;
+		match i32 -> i32 { double(x).await }
+		else -> i32 { 0 }
+	};
+	assert_eq!(block_on(future), 42);
+
+	let future = spez! {
+		async for "a";
+		match i32 -> i32 { 1 }
+		else -> i32 { 2 }
+	};
+	assert_eq!(block_on(future), 2);
+}
+
+#[test]
+fn async_guard_falls_through() {
+	let f = |v: i32| {
+		spez! {
+			async for v;
+			match i32 if v > 100 -> i32 { double(v).await }
+			match i32 -> i32 { v }
+		}
+	};
+	assert_eq!(block_on(f(1000)), 2000);
+	assert_eq!(block_on(f(1)), 1);
+}
+
+#[test]
+fn async_impl_trait() {
+	let future = spez! {
+		async for x = 3u8;
+		match<T: Clone> T -> impl Clone { x.clone() }
+	};
+	let _ = block_on(future);
+}
+
+#[test]
+fn async_body_runs_on_poll() {
+	let ran = std::cell::Cell::new(false);
+	let future = spez! {
+		async for x = &ran;
+		match &std::cell::Cell<bool> { x.set(true); }
+	};
+	assert!(!ran.get());
+	block_on(future);
+	assert!(ran.get());
+}
+
+#[test]
+fn async_unused_input_lives_until_completion() {
+	struct SetOnDrop<'a>(&'a std::cell::Cell<bool>);
+	impl Drop for SetOnDrop<'_> {
+		fn drop(&mut self) {
+			self.0.set(true);
+		}
+	}
+	let dropped = std::cell::Cell::new(false);
+	let future = spez! {
+		async for SetOnDrop(&dropped);
+		match<'a> SetOnDrop<'a> {}
+	};
+	assert!(!dropped.get());
+	block_on(future);
+	assert!(dropped.get());
+}
+
+#[test]
+fn deferred_bodies() {
+	use spez::spez_fn;
+
+	let f = spez_fn! {
+		for x = 21i32;
+		match i32 -> i32 { x * 2 }
+		else -> i32 { 0 }
+	};
+	assert_eq!(f(), 42);
+
+	let f = spez_fn! {
+		for x = String::from("abc");
+		match String -> String { x + "def" }
+	};
+	assert_eq!(f(), "abcdef");
+}
+
+#[test]
+fn async_deferred_bodies() {
+	use spez::spez_fn;
+	use std::cell::Cell;
+	use std::rc::Rc;
+
+	async fn double(x: i32) -> i32 {
+		x * 2
+	}
+	let flag = Rc::new(Cell::new(false));
+	let ran = Rc::clone(&flag);
+	let f = spez_fn! {
+		async for x = 21i32;
+		capture(ran: Rc<Cell<bool>>);
+		match i32 if x > 100 -> i32 { 0 }
+		match i32 -> i32 { ran.set(true); double(x).await }
+		else -> i32 { -1 }
+	};
+	let future = f();
+	assert!(!flag.get());
+	assert_eq!(block_on(future), 42);
+	assert!(flag.get());
+
+	let s = String::from("abc");
+	let f = spez_fn! {
+		async for x = s;
+		match String -> usize { async { x.len() }.await }
+	};
+	assert_eq!(block_on(f()), 3);
+}
+
+#[test]
+fn deferred_guard_falls_through() {
+	use spez::spez_fn;
+
+	let f = |v: i32| {
+		spez_fn! {
+			for v;
+			match i32 if v > 100 -> i32 { v * 2 }
+			match i32 -> i32 { v }
+		}
+	};
+	assert_eq!(f(1000)(), 2000);
+	assert_eq!(f(1)(), 1);
+}
+
+#[test]
+fn deferred_body_runs_on_call() {
+	use spez::spez_fn;
+
+	let ran = std::cell::Cell::new(false);
+	let f = spez_fn! {
+		for x = &ran;
+		match &std::cell::Cell<bool> { x.set(true); }
+	};
+	assert!(!ran.get());
+	f();
+	assert!(ran.get());
+}
+
+#[test]
+fn user_types_named_like_generated_items() {
+	struct Match(u8);
+	struct Layer;
+	trait Match1 {
+		fn spez(&self) -> u8 {
+			2
+		}
+	}
+	impl Match1 for Layer {}
+
+	let result = spez! {
+		for x = Match(1);
+		match Layer -> u8 { 0 }
+		match Match -> u8 { x.0 }
+	};
+	assert_eq!(result, 1);
+	assert_eq!(Layer.spez(), 2);
+}
+
+#[test]
+fn nested() {
+	let result = spez! {
+		for x = (1u8, "ab");
+		match (u8, &str) -> usize {
+			spez! {
+				for y = x.1;
+				match &str -> usize { y.len() }
+				else -> usize { 0 }
+			}
+		}
+		else -> usize { 0 }
+	};
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn many_arms() {
+	macro_rules! arms {
+		($input:expr; $($n:literal)*) => {
+			spez! {
+				for x = $input;
+				$(match [u8; $n] -> usize { $n })*
+				match<T> T -> usize { 0 }
+				#[allow(deprecated)]
+				match<T: Copy> T -> usize { 100 }
+			}
+		};
+	}
+	let f = |input| {
+		arms! {
+			input;
+			1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25
+			26 27 28 29 30 31 32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48
+			49 50 51 52 53 54 55 56 57 58 59 60
+		}
+	};
+	assert_eq!(f([0u8; 1]), 1);
+	assert_eq!(arms!([0u8; 60]; 1 2 3 60), 60);
+	assert_eq!(arms!(0u8; 1 2 3 60), 0);
+}
+
+#[test]
+#[deny(deprecated)]
+fn unreachable_arms_can_be_allowed() {
+	let result = spez! {
+		for x = 1u8;
+		match<T> T -> i32 { 0 }
+		#[allow(deprecated)]
+		match u8 -> i32 { 1 }
+		#[allow(deprecated)]
+		else -> i32 { 2 }
+	};
+	assert_eq!(result, 0);
+}
+
+#[test]
+fn same_type_in_different_kinds_of_arms() {
+	let f = |v: i32| {
+		spez! {
+			for x = v;
+			match i32 if x > 0 -> i32 { 1 }
+			match i32 | i64 if x < 0 -> i32 { 2 }
+			match i32 -> i32 { 3 }
+		}
+	};
+	assert_eq!(f(5), 1);
+	assert_eq!(f(-5), 2);
+	assert_eq!(f(0), 3);
+}
+
+#[test]
+fn slices_and_arrays() {
+	let bytes: &[u8] = &[0xab, 0x01];
+	let result = spez! {
+		for s = bytes;
+		match &[i32] -> String { String::from("i32") }
+		match &[u8] -> String {
+			s.iter().map(|b| format!("{:02x}", b)).collect()
+		}
+		match &[char] -> String { s.iter().collect() }
+	};
+	assert_eq!(result, "ab01");
+
+	let chars: &[char] = &['h', 'i'];
+	let result = spez! {
+		for s = chars;
+		match &[u8] -> String { String::new() }
+		match &[char] -> String { s.iter().collect() }
+	};
+	assert_eq!(result, "hi");
+
+	let result = spez! {
+		for a = [1i32, 2, 3];
+		match [u8; 3] -> i32 { 0 }
+		match<const N: usize> [i32; N] -> i32 { a.iter().sum() }
+	};
+	assert_eq!(result, 6);
+}
+
+#[test]
+fn string_and_str() {
+	macro_rules! kind {
+		($e:expr) => {
+			spez! {
+				for s = $e;
+				match String -> &'static str { "String" }
+				match &str -> &'static str { "&str" }
+				else -> &'static str { "?" }
+			}
+		};
+	}
+	let owned = String::from("a");
+	let slice: &str = "b";
+	assert_eq!(kind!(owned.clone()), "String");
+	assert_eq!(kind!(slice), "&str");
+	assert_eq!(kind!("c"), "&str");
+	assert_eq!(kind!(owned.as_str()), "&str");
+	assert_eq!(kind!(&owned), "?");
+	assert_eq!(kind!(Box::<str>::from("d")), "?");
+
+	let len = spez! {
+		for s = &owned;
+		match<S: AsRef<str>> S -> usize { s.as_ref().len() }
+		else -> usize { 0 }
+	};
+	assert_eq!(len, 1);
+}
+
+#[test]
+fn trait_objects() {
+	use std::any::Any;
+	use std::fmt::Display;
+
+	let d: &dyn Display = &1;
+	let result = spez! {
+		for d;
+		match i32 -> String { String::new() }
+		match &dyn Display -> String { d.to_string() }
+	};
+	assert_eq!(result, "1");
+
+	// A reference to a concrete type is not a trait object.
+	let result = spez! {
+		for &1;
+		match &dyn Display -> u8 { 0 }
+		match &i32 -> u8 { 1 }
+	};
+	assert_eq!(result, 1);
+
+	let b: Box<dyn Any> = Box::new(2u8);
+	let result = spez! {
+		for b;
+		match Box<dyn Display> -> u8 { 0 }
+		match Box<dyn Any> -> u8 { *b.downcast::<u8>().unwrap() }
+	};
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn raw_pointers() {
+	let a = 1u8;
+	let mut b = 2u8;
+	macro_rules! which {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match *const u8 -> u8 { 1 }
+				match *mut u8 -> u8 { 2 }
+				match<T: ?Sized> *const T -> u8 { 3 }
+				else -> u8 { 0 }
+			}
+		};
+	}
+	assert_eq!(which!(&a as *const u8), 1);
+	assert_eq!(which!(&mut b as *mut u8), 2);
+	assert_eq!(which!(&a as *const u8 as *const ()), 3);
+	assert_eq!(which!(&a), 0);
+	let p = spez! {
+		for x = &a as *const u8;
+		match *const u8 -> *const u8 { x }
+	};
+	assert_eq!(p, &a as *const u8);
+}
+
+#[test]
+fn type_macros() {
+	macro_rules! int {
+		() => {
+			i32
+		};
+	}
+	macro_rules! vec_of {
+		($t:ty) => {
+			Vec<$t>
+		};
+	}
+	macro_rules! which {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match int!() -> u8 { 1 }
+				match vec_of!(u8) | &vec_of!(u8) -> u8 { 2 }
+				match<T> vec_of!(T) -> u8 { 3 }
+				else -> u8 { 0 }
+			}
+		};
+	}
+	assert_eq!(which!(1i32), 1);
+	assert_eq!(which!(vec![1u8]), 2);
+	assert_eq!(which!(&vec![1u8]), 2);
+	assert_eq!(which!(vec![1u16]), 3);
+	assert_eq!(which!(1i64), 0);
+	let result = spez! {
+		for x = 1i32;
+		match int!() -> int!() { x + 1 }
+	};
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn reference_depth() {
+	let result = spez! {
+		for &&5;
+		match &i32 -> u8 { 1 }
+		match &&i32 -> u8 { 2 }
+		match i32 -> u8 { 0 }
+	};
+	assert_eq!(result, 2);
+
+	let result = spez! {
+		for &5;
+		match &&i32 -> u8 { 2 }
+		match &i32 -> u8 { 1 }
+		match i32 -> u8 { 0 }
+	};
+	assert_eq!(result, 1);
+
+	let result = spez! {
+		for x = &&5;
+		match<T: Copy + Into<i32>> &&T -> i32 { (**x).into() }
+		else -> i32 { 0 }
+	};
+	assert_eq!(result, 5);
+}
+
+#[test]
+fn early_return() {
+	fn f(v: i32) -> i32 {
+		let result = spez! {
+			for x = v;
+			match i32 -> i32 {
+				if x < 0 {
+					return 0;
+				}
+				x * 2
+			}
+			else -> i32 { -1 }
+		};
+		result + 1
+	}
+	assert_eq!(f(-5), 1);
+	assert_eq!(f(5), 11);
+}
+
+#[test]
+fn early_return_from_guarded_arm() {
+	let f = |v: i32| {
+		spez! {
+			for x = v;
+			match i32 if x != 0 -> &'static str {
+				if x < 0 {
+					return "negative";
+				}
+				"positive"
+			}
+			match i32 -> &'static str { "zero" }
+		}
+	};
+	assert_eq!(f(-1), "negative");
+	assert_eq!(f(1), "positive");
+	assert_eq!(f(0), "zero");
+}
+
+#[test]
+fn question_mark() {
+	#[derive(Debug, PartialEq)]
+	struct Error;
+
+	fn check(v: i32) -> Result<i32, Error> {
+		if v < 0 {
+			Err(Error)
+		} else {
+			Ok(v)
+		}
+	}
+
+	fn f(v: i32) -> Result<i32, Error> {
+		let n = spez! {
+			for x = v;
+			match i32 -> Result<i32, Error> {
+				let x = check(x)?;
+				Ok(x + 1)
+			}
+			else -> Result<i32, Error> { Ok(0) }
+		}?;
+		Ok(n * 10)
+	}
+
+	assert_eq!(f(1), Ok(20));
+	assert_eq!(f(-1), Err(Error));
+}
+
+#[test]
+fn priority() {
+	macro_rules! describe {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match<T: std::fmt::Debug> T -> &'static str { "debug" }
+				match(priority = 3) u8 -> &'static str { "u8" }
+				match(priority = 1) i32 if x > 0 -> &'static str { "positive" }
+				match(priority = 2) i32 -> &'static str { "i32" }
+				match(priority = 2) <T: Copy> T -> &'static str { "copy" }
+				else -> &'static str { "other" }
+			}
+		};
+	}
+	assert_eq!(describe!(1i32), "positive");
+	assert_eq!(describe!(-1i32), "i32");
+	assert_eq!(describe!(1u8), "copy");
+	assert_eq!(describe!(String::new()), "debug");
+	assert_eq!(describe!(vec![|| ()]), "other");
+
+	// Arms generated in the wrong order.
+	macro_rules! arms {
+		($($priority:literal [$($arm:tt)*] => $value:expr,)*) => {
+			spez! {
+				for 1u8;
+				$(match(priority = $priority) $($arm)* -> i32 { $value })*
+			}
+		};
+	}
+	assert_eq!(arms!(2 [<T: Copy> T] => 0, 1 [u8] => 1,), 1);
+
+	// An arm with a `!` bound keeps the arm after it.
+	struct NoDebug;
+	macro_rules! debug {
+		($e:expr) => {
+			spez! {
+				for $e;
+				// Never selected, as the arms with a priority match any type.
+				#[allow(deprecated)]
+				match i32 -> &'static str { "i32" }
+				match(priority = 1) <T> T where !std::fmt::Debug -> &'static str { "not debug" }
+				else -> &'static str { "debug" }
+			}
+		};
+	}
+	assert_eq!(debug!(NoDebug), "not debug");
+	assert_eq!(debug!(1i32), "debug");
+
+	// The index is the position as written, not the order the arms are tried in.
+	let result = spez::spez_with_index! {
+		for x = (1i32, 2i32);
+		match (i32, i32) -> i32 { x.0 }
+		match(priority = 0) <T: Copy> (T, T) -> i32 { 0 }
+	};
+	assert_eq!(result, (0, 1));
+	macro_rules! debug_index {
+		($e:expr) => {
+			spez::spez_with_index! {
+				for $e;
+				#[allow(deprecated)]
+				match i32 {}
+				match(priority = 1) <T> T where !std::fmt::Debug {}
+				else {}
+			}
+		};
+	}
+	assert_eq!(debug_index!(NoDebug), ((), 1));
+	assert_eq!(debug_index!(1i32), ((), 2));
+}
+
+#[test]
+fn with_index() {
+	use spez::spez_with_index;
+
+	let f = |v: i32| {
+		spez_with_index! {
+			for x = v;
+			match i64 -> i32 { 0 }
+			match i32 if x > 10 -> i32 {
+				if x > 100 {
+					return 100;
+				}
+				x
+			}
+			match i32 -> i32 { -x }
+		}
+	};
+	assert_eq!(f(1000), (100, 1));
+	assert_eq!(f(50), (50, 1));
+	assert_eq!(f(5), (-5, 2));
+
+	// The index is that of the arm as written, also for an arm with a `!`
+	// bound, which is tried after the arm that follows it.
+	struct NoDebug;
+	macro_rules! debug {
+		($e:expr) => {
+			spez_with_index! {
+				for $e;
+				match<T> T where !std::fmt::Debug {}
+				else {}
+			}
+		};
+	}
+	assert_eq!(debug!(NoDebug), ((), 0));
+	assert_eq!(debug!(1i32), ((), 1));
+
+	let (iter, index) = spez_with_index! {
+		for x = 3;
+		match i32 -> impl Iterator<Item = i32> { 0..x }
+	};
+	assert_eq!(iter.sum::<i32>(), 3);
+	assert_eq!(index, 0);
+
+	let ((), index) = spez_with_index! {
+		for "a";
+		match i32 {}
+		else {}
+	};
+	assert_eq!(index, 1);
+
+	let future = spez_with_index! {
+		async for x = 2;
+		match i32 if x > 5 -> i32 { x }
+		match i32 -> i32 { double(x).await }
+	};
+	assert_eq!(block_on(future), (4, 1));
+}
+
+#[test]
+fn prefix() {
+	let result = spez! {
+		#[spez(prefix = "Outer")]
+		for x = 1u8;
+		match u8 -> u8 {
+			spez! {
+				#[spez(prefix = "Inner")]
+				for y = x;
+				match u8 -> u8 { y + 1 }
+			}
+		}
+	};
+	assert_eq!(result, 2);
+}
+
+#[cfg(feature = "debug-expansion")]
+#[test]
+fn debug_expansion() {
+	// The names of the traits are made from the types, so these have to be
+	// turned into valid identifiers.
+	macro_rules! describe {
+		($e:expr) => {
+			spez! {
+				for $e;
+				match () -> &'static str { "unit" }
+				match &'static str -> &'static str { "str" }
+				match [u8; 4] -> &'static str { "array" }
+				match fn(u8) -> u8 -> &'static str { "fn" }
+				match Vec<u8> | Vec<u16> -> &'static str { "vec" }
+				match<T: ?Sized> &'static T -> &'static str { "ref" }
+				else -> &'static str { "other" }
+			}
+		};
+	}
+	fn double(x: u8) -> u8 {
+		x * 2
+	}
+	assert_eq!(describe!(()), "unit");
+	assert_eq!(describe!("a"), "str");
+	assert_eq!(describe!([0u8; 4]), "array");
+	assert_eq!(describe!(double as fn(u8) -> u8), "fn");
+	assert_eq!(describe!(vec![0u16]), "vec");
+	assert_eq!(describe!(&0u8), "ref");
+	assert_eq!(describe!(0u8), "other");
+}
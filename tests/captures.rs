@@ -0,0 +1,74 @@
+use spez::spez;
+
+#[test]
+fn capture_value() {
+	let a = 1;
+	let s = String::from("abc");
+	let f = |v: i32| {
+		let s = s.clone();
+		spez! {
+			for x = v;
+			capture(a: i32, s: String);
+			match i64 -> String { String::new() }
+			match i32 -> String { format!("{}{}", s, a + x) }
+		}
+	};
+	assert_eq!(f(2), "abc3");
+}
+
+#[test]
+fn capture_mutable_reference() {
+	let mut v = Vec::new();
+	let r = &mut v;
+	spez! {
+		for x = 1u8;
+		capture(r: &mut Vec<u8>);
+		match u8 { r.push(x); }
+		else {}
+	}
+	let r = &mut v;
+	spez! {
+		for x = "a";
+		capture(r: &mut Vec<u8>);
+		match u8 { r.push(x); }
+		else { r.push(0); }
+	}
+	assert_eq!(v, [1, 0]);
+}
+
+#[test]
+fn capture_with_guard() {
+	let limit = 10;
+	let f = |v: i32| {
+		spez! {
+			for x = v;
+			capture(limit: i32);
+			match i32 if x > limit -> i32 { limit }
+			match i32 -> i32 { x }
+		}
+	};
+	assert_eq!(f(5), 5);
+	assert_eq!(f(50), 10);
+}
+
+#[test]
+fn prelude() {
+	let runs = &std::cell::Cell::new(0);
+	let f = |v: u8| {
+		spez! {
+			for x = v;
+			capture(runs: &std::cell::Cell<u32>);
+			prelude {
+				runs.set(runs.get() + 1);
+				let double = x * 2;
+				let unused = 0;
+			}
+			match u8 if x > 5 -> u8 { double }
+			match u8 -> u8 { double + 1 }
+		}
+	};
+	assert_eq!(f(1), 3);
+	assert_eq!(runs.get(), 1);
+	assert_eq!(f(6), 12);
+	assert_eq!(runs.get(), 2);
+}
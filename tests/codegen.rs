@@ -0,0 +1,32 @@
+//! Checks that the dispatch of spez! compiles away with optimizations.
+
+mod common;
+
+#[test]
+fn dispatch_is_inlined() {
+	let ir = common::llvm_ir(
+		"codegen",
+		"#[no_mangle]
+		pub fn spez_codegen(x: u32) -> u32 {
+			spez::spez! {
+				for x;
+				match u8 -> u32 { 0 }
+				match u16 -> u32 { 1 }
+				match u32 -> u32 { x.wrapping_add(1) }
+				else -> u32 { 2 }
+			}
+		}",
+	);
+	let defines = ir
+		.lines()
+		.filter(|line| line.starts_with("define "))
+		.collect::<Vec<_>>();
+	// Only the function itself is left, without calls to the methods of the
+	// arms or to `deref`. With more than one codegen unit (the default), this
+	// needs these methods to be `#[inline]`.
+	assert_eq!(defines.len(), 1, "{}", ir);
+	assert!(defines[0].contains("@spez_codegen("), "{}", ir);
+	let body = &ir[ir.find(defines[0]).unwrap()..];
+	let body = &body[..body.find("\n}").unwrap()];
+	assert!(!body.contains("call "), "{}", body);
+}
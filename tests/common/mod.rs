@@ -0,0 +1,87 @@
+//! Builds small crates that use spez, to check the errors it reports and the
+//! code it generates.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Runs cargo with `args` on a crate named `name`, with `source` as its
+/// `lib.rs`.
+fn cargo(name: &str, args: &[&str], env: &[(&str, &str)], source: &str) -> Output {
+	let tmp = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+	let dir = tmp.join(name);
+	std::fs::create_dir_all(&dir).unwrap();
+	let manifest = format!(
+		"[package]\n\
+		 name = {:?}\n\
+		 version = \"0.0.0\"\n\
+		 edition = \"2021\"\n\
+		 [lib]\n\
+		 path = \"lib.rs\"\n\
+		 [dependencies]\n\
+		 spez = {{ path = {:?} }}\n\
+		 [workspace]\n",
+		name,
+		env!("CARGO_MANIFEST_DIR"),
+	);
+	std::fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+	std::fs::write(dir.join("lib.rs"), source).unwrap();
+	let cargo = std::env::var("CARGO").unwrap_or_else(|_| String::from("cargo"));
+	Command::new(cargo)
+		.args(["--offline", "--quiet", "--color=never"])
+		.args(args)
+		.current_dir(&dir)
+		// Shared by all these crates, so spez is only built once.
+		.env("CARGO_TARGET_DIR", tmp.join("build"))
+		.envs(env.iter().copied())
+		.env_remove("RUSTFLAGS")
+		.output()
+		.unwrap()
+}
+
+/// Builds a crate named `name` with `source` as its `lib.rs`, and returns the
+/// errors.
+#[allow(dead_code)]
+pub fn build(name: &str, env: &[(&str, &str)], source: &str) -> Result<(), String> {
+	let output = cargo(name, &["check"], env, source);
+	match output.status.success() {
+		true => Ok(()),
+		false => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+	}
+}
+
+/// Builds a crate with `source` as its `lib.rs`, and checks that it fails
+/// with `message`.
+#[allow(dead_code)]
+pub fn assert_error(name: &str, source: &str, message: &str) {
+	let error = build(name, &[], source).unwrap_err();
+	assert!(error.contains(message), "{}", error);
+}
+
+/// Compiles a crate with `source` as its `lib.rs` with optimizations, and
+/// returns its LLVM IR.
+#[allow(dead_code)]
+pub fn llvm_ir(name: &str, source: &str) -> String {
+	let output = cargo(
+		name,
+		&["rustc", "--release", "--lib", "--", "--emit=llvm-ir"],
+		&[],
+		source,
+	);
+	assert!(
+		output.status.success(),
+		"{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+	let deps = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("build/release/deps");
+	let prefix = format!("{}-", name);
+	let file = std::fs::read_dir(deps)
+		.unwrap()
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| {
+			let file_name = path.file_name().unwrap().to_string_lossy();
+			file_name.starts_with(&prefix) && file_name.ends_with(".ll")
+		})
+		.max_by_key(|path| path.metadata().unwrap().modified().unwrap())
+		.unwrap();
+	std::fs::read_to_string(file).unwrap()
+}
@@ -0,0 +1,69 @@
+use spez::Spez;
+
+#[derive(Debug, PartialEq, Spez)]
+enum Payload {
+	Number(i32),
+	Text(String),
+	Bytes(Vec<u8>),
+}
+
+#[derive(Debug, PartialEq, Spez)]
+#[spez(name = "as_id")]
+enum Id {
+	Short(u16),
+	Long(u64),
+}
+
+mod reexport {
+	pub use spez::spez;
+}
+
+#[derive(Debug, PartialEq, Spez)]
+#[spez(crate = crate::reexport)]
+enum Flag {
+	Bool(bool),
+}
+
+#[test]
+fn dispatch() {
+	assert_eq!(payload!(5), Some(Payload::Number(5)));
+	assert_eq!(
+		payload!(String::from("a")),
+		Some(Payload::Text(String::from("a")))
+	);
+	assert_eq!(payload!(vec![1u8, 2]), Some(Payload::Bytes(vec![1, 2])));
+	assert_eq!(payload!(1.5f32), None);
+	assert_eq!(payload!("a"), None);
+}
+
+#[test]
+fn dispatch_in_a_macro() {
+	macro_rules! describe {
+		($e:expr) => {
+			match payload!($e) {
+				Some(Payload::Number(n)) => format!("number {n}"),
+				Some(Payload::Text(s)) => format!("text {s}"),
+				Some(Payload::Bytes(b)) => format!("{} bytes", b.len()),
+				None => String::from("unknown"),
+			}
+		};
+	}
+	assert_eq!(describe!(1 + 1), "number 2");
+	assert_eq!(describe!(String::from("abc")), "text abc");
+	assert_eq!(describe!(b"ab".to_vec()), "2 bytes");
+	assert_eq!(describe!(()), "unknown");
+}
+
+#[test]
+fn name() {
+	let value = 7u16;
+	assert_eq!(as_id!(value), Some(Id::Short(7)));
+	assert_eq!(as_id!(7u64), Some(Id::Long(7)));
+	assert_eq!(as_id!(7u32), None);
+}
+
+#[test]
+fn crate_path() {
+	assert_eq!(flag!(true), Some(Flag::Bool(true)));
+	assert_eq!(flag!(1), None);
+}
@@ -0,0 +1,90 @@
+//! The errors reported for code that can't work, checked by building small
+//! crates.
+
+mod common;
+
+use common::assert_error;
+
+#[test]
+fn spez_ref_tuple() {
+	// Building the tuple would move the variables.
+	assert_error(
+		"spez_ref_tuple",
+		"pub fn f(a: String, b: String) -> usize {
+			let n = spez::spez_ref! {
+				for (a, b);
+				match (String, String) -> usize { 2 }
+			};
+			a.len() + b.len() + n
+		}",
+		"spez_ref! can not borrow a tuple of variables",
+	);
+}
+
+#[test]
+fn capture_each() {
+	// Every input moves the captured variable.
+	assert_error(
+		"capture_each",
+		"pub fn f(s: String) -> [usize; 2] {
+			spez::spez! {
+				for x in [1u8, 2u8] -> usize;
+				capture(s: String);
+				match u8 { drop(s); 1 }
+			}
+		}",
+		"use of moved value: `s`",
+	);
+}
+
+#[test]
+fn mut_input() {
+	assert_error(
+		"mut_input",
+		"pub fn f() -> i32 {
+			spez::spez! {
+				for mut x = 1;
+				match i32 -> i32 { x += 1; x }
+			}
+		}",
+		"the input is already mutable in every arm, so `mut` is not needed",
+	);
+}
+
+#[test]
+fn defaults() {
+	assert_error(
+		"duplicate_default",
+		"pub fn f() -> i32 {
+			spez::spez! {
+				for 1i32;
+				match i32 -> i32 { 1 }
+				default -> i32 { 2 }
+				default -> i32 { 3 }
+			}
+		}",
+		"the `default` arm must be the last arm",
+	);
+	assert_error(
+		"default_not_last",
+		"pub fn f() -> i32 {
+			spez::spez! {
+				for 1i32;
+				else -> i32 { 2 }
+				match i32 -> i32 { 1 }
+			}
+		}",
+		"the `else` arm must be the last arm",
+	);
+	assert_error(
+		"require_default",
+		"pub fn f() -> i32 {
+			spez::spez! {
+				#[spez(require_default)]
+				for 1i32;
+				match i32 -> i32 { 1 }
+			}
+		}",
+		"`#[spez(require_default)]` requires a `default` or `else` arm",
+	);
+}
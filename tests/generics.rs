@@ -0,0 +1,458 @@
+use spez::spez;
+
+#[test]
+fn const_generics() {
+	let result = spez! {
+		for [1u8, 2, 3, 4];
+		match<const N: usize> [u8; N] -> usize { N }
+		match _ -> usize { 0 }
+	};
+	assert_eq!(result, 4);
+	let result = spez! {
+		for x = [1u8, 2, 3, 4];
+		match [u8; 3] -> usize { 3 }
+		match<T: Copy + Into<usize>, const N: usize> [T; N] -> usize {
+			x.iter().map(|&v| v.into()).sum::<usize>() * N
+		}
+	};
+	assert_eq!(result, 40);
+}
+
+#[test]
+fn predicates_without_bounds() {
+	let result = spez! {
+		for [1u8, 2, 3];
+		match<const N: usize> [u8; N] where [u8; N]: -> usize { N }
+	};
+	assert_eq!(result, 3);
+	let result = spez! {
+		for x = 5u8;
+		match<T> T where T:, T: Copy if true -> u8 { 1 }
+		match<T> T where T: -> u8 { 2 }
+	};
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn type_parameters_in_body() {
+	use std::any::type_name;
+	let result = spez! {
+		for x = vec![1u8];
+		match<T: Copy> Vec<T> -> (&'static str, usize) {
+			let _ = x.into_iter().rev().collect::<Vec<T>>();
+			(type_name::<T>(), std::mem::size_of::<T>())
+		}
+	};
+	assert_eq!(result, ("u8", 1));
+	let result = spez! {
+		for x = (1u16, 'a');
+		match<A, B: Into<u32>> (A, B) where A: Into<u32> -> u32 {
+			let (a, b) = x;
+			<A as Into<u32>>::into(a) + Into::<u32>::into(b)
+		}
+	};
+	assert_eq!(result, 98);
+}
+
+#[test]
+fn lifetime_generics() {
+	let s = String::from("hello");
+	let result = spez! {
+		for x = s.as_str();
+		match<'a> &'a String -> usize { 0 }
+		match<'a> &'a str -> usize { x.len() }
+	};
+	assert_eq!(result, 5);
+	let result = spez! {
+		for x = s.as_str();
+		match<'a, T: ?Sized + AsRef<[u8]>> &'a T -> &'a [u8] { x.as_ref() }
+		match _ -> &'static [u8] { &[] }
+	};
+	assert_eq!(result, b"hello");
+}
+
+#[test]
+fn elided_lifetimes_in_return_type() {
+	let s = String::from("hello");
+	let result = spez! {
+		for x = &s;
+		match &String -> &str { x.as_str() }
+	};
+	assert_eq!(result, "hello");
+	let result = spez! {
+		for &s;
+		match String -> (&str, usize) { (s.as_str(), s.len()) }
+		else -> (&str, usize) { ("", 0) }
+	};
+	assert_eq!(result, ("hello", 5));
+	let v = vec![1, 2, 3];
+	let result = spez! {
+		for x = v.as_slice();
+		match<'a> &'a [i32] if x.is_empty() -> Option<&i32> { None }
+		match &[i32] -> Option<&'_ i32> { x.last() }
+	};
+	assert_eq!(result, Some(&3));
+}
+
+#[test]
+fn std_wrappers() {
+	use std::borrow::Cow;
+	use std::rc::Rc;
+	macro_rules! kind {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match Cow<'_, str> -> String { format!("cow {}", x) }
+				match String -> String { format!("string {}", x) }
+				match &str -> String { format!("str {}", x) }
+				match<'a> Cow<'a, [u8]> -> String { format!("bytes {}", x.len()) }
+				match Rc<str> | Box<str> -> String { format!("pointer {}", x) }
+			}
+		};
+	}
+	assert_eq!(kind!(Cow::Borrowed("a")), "cow a");
+	assert_eq!(kind!(Cow::<str>::Owned(String::from("b"))), "cow b");
+	assert_eq!(kind!(String::from("c")), "string c");
+	assert_eq!(kind!("d"), "str d");
+	assert_eq!(kind!(Cow::Borrowed(&b"xy"[..])), "bytes 2");
+	assert_eq!(kind!(Rc::<str>::from("e")), "pointer e");
+	assert_eq!(kind!(Box::<str>::from("f")), "pointer f");
+
+	let s = String::from("abc");
+	let result = spez! {
+		for x = Cow::Borrowed(s.as_str());
+		match Cow<'_, str> -> Self { x }
+	};
+	assert!(matches!(result, Cow::Borrowed("abc")));
+}
+
+#[test]
+fn phantom_data() {
+	use std::any::type_name;
+	use std::marker::PhantomData;
+	struct Marker;
+	macro_rules! name {
+		($e:expr) => {
+			spez! {
+				for $e;
+				match PhantomData<u8> -> &'static str { "u8" }
+				match<T: Copy + 'static> PhantomData<T> -> &'static str { type_name::<T>() }
+				match<T: ?Sized> PhantomData<T> -> &'static str { "other" }
+				match Marker -> &'static str { "marker" }
+				match () -> &'static str { "unit" }
+				else -> &'static str { "?" }
+			}
+		};
+	}
+	assert_eq!(name!(PhantomData::<u8>), "u8");
+	assert_eq!(name!(PhantomData::<i64>), "i64");
+	assert_eq!(name!(PhantomData::<(char, bool)>), "(char, bool)");
+	assert_eq!(name!(PhantomData::<String>), "other");
+	assert_eq!(name!(PhantomData::<str>), "other");
+	assert_eq!(name!(Marker), "marker");
+	assert_eq!(name!(()), "unit");
+	assert_eq!(name!([0u8; 0]), "?");
+}
+
+#[test]
+fn unsized_types() {
+	use spez::spez_ref;
+	macro_rules! kind {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match<T> &T -> &'static str { "sized" }
+				match<T: ?Sized> &T -> &'static str { "unsized" }
+				else -> &'static str { "value" }
+			}
+		};
+	}
+	let s: &str = "abc";
+	let v: &[u8] = &[1, 2];
+	assert_eq!(kind!(s), "unsized");
+	assert_eq!(kind!(v), "unsized");
+	assert_eq!(kind!(&1u8), "sized");
+	assert_eq!(kind!(1u8), "value");
+
+	macro_rules! len {
+		($e:expr) => {
+			spez_ref! {
+				for x = $e;
+				match str -> usize { x.len() }
+				match<T> [T] -> usize { x.len() + 100 }
+				match<T: ?Sized> T -> usize { 0 }
+			}
+		};
+	}
+	assert_eq!(len!(*s), 3);
+	assert_eq!(len!(*v), 102);
+	assert_eq!(len!(1u8), 0);
+
+	// The input is always sized, so `?Sized` makes no difference here.
+	let result = spez! {
+		for x = 1u8;
+		match<T: ?Sized + ToString> T -> String { x.to_string() }
+	};
+	assert_eq!(result, "1");
+	let result = spez! {
+		for x = 2u8;
+		match<T> T where T: ?Sized + ToString -> String { x.to_string() }
+	};
+	assert_eq!(result, "2");
+}
+
+#[test]
+fn function_pointers_and_closures() {
+	fn double(x: i32) -> i32 {
+		x * 2
+	}
+	let result = spez! {
+		for f = double as fn(i32) -> i32;
+		match fn(i32) -> i32 -> i32 { f(10) }
+		match<F: Fn(i32) -> i32> F -> i32 { -f(10) }
+	};
+	assert_eq!(result, 20);
+
+	let offset = 1;
+	let result = spez! {
+		for f = move |x: i32| x + offset;
+		match fn(i32) -> i32 -> i32 { f(10) }
+		match<F: Fn(i32) -> i32> F -> i32 { -f(10) }
+	};
+	assert_eq!(result, -11);
+}
+
+#[test]
+fn higher_ranked_bounds() {
+	let result = spez! {
+		for f = |s: &str| s.len();
+		match<F> F where F: for<'a> Fn(&'a str) -> usize -> usize { f("abc") }
+		else -> usize { 0 }
+	};
+	assert_eq!(result, 3);
+
+	let result = spez! {
+		for f = str::len;
+		match<F: for<'a> Fn(&'a str) -> usize> F -> usize { f("ab") }
+		else -> usize { 0 }
+	};
+	assert_eq!(result, 2);
+
+	let result = spez! {
+		for f = |x: i32| x;
+		match<F: for<'a> Fn(&'a str) -> usize> F -> usize { f("ab") }
+		else -> usize { 0 }
+	};
+	assert_eq!(result, 0);
+
+	let result = spez! {
+		for f = str::trim;
+		match<F> F where for<'a> F: Fn(&'a str) -> &'a str -> usize { f(" abc ").len() }
+		else -> usize { 0 }
+	};
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn function_pointer_input_with_return_type() {
+	fn double(x: i32) -> i32 {
+		x * 2
+	}
+	let result = spez! {
+		for f = (double as fn(i32) -> i32) -> i32;
+		match fn(i32) -> i32 { f(1) }
+		else { 0 }
+	};
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn associated_type_bounds() {
+	use std::fmt::Debug;
+
+	let result = spez! {
+		for iter = vec![1, 2].into_iter();
+		match<I> I where I: Iterator, I::Item: Debug -> String {
+			iter.map(|item| format!("{:?}", item)).collect::<Vec<_>>().join(",")
+		}
+		else -> String { String::from("?") }
+	};
+	assert_eq!(result, "1,2");
+
+	struct NoDebug;
+	let result = spez! {
+		for iter = vec![NoDebug].into_iter();
+		match<I> I where I: Iterator, I::Item: Debug -> String {
+			iter.map(|item| format!("{:?}", item)).collect::<Vec<_>>().join(",")
+		}
+		else -> String { String::from("?") }
+	};
+	assert_eq!(result, "?");
+
+	let result = spez! {
+		for iter = "ab".bytes();
+		match<I> I where I: Iterator<Item = char> -> u32 { 0 }
+		match<I: Iterator<Item = u8>> I -> u32 { iter.map(u32::from).sum() }
+	};
+	assert_eq!(result, 97 + 98);
+}
+
+#[test]
+fn generic_parameter_of_the_function() {
+	use std::marker::PhantomData;
+	fn f<U: Copy>() -> u8 {
+		spez! {
+			for (1, PhantomData::<U>);
+			match<V: Copy> (i32, PhantomData<V>) -> u8 { 1 }
+			else -> u8 { 0 }
+		}
+	}
+	fn g<U>() -> u8 {
+		spez! {
+			for (1, PhantomData::<U>);
+			match<V: Copy> (i32, PhantomData<V>) -> u8 { 1 }
+			else -> u8 { 0 }
+		}
+	}
+	assert_eq!(f::<u8>(), 1);
+	assert_eq!(g::<u8>(), 0);
+}
+
+#[test]
+fn impl_trait_types() {
+	use std::fmt::Debug;
+	struct NoDebugType;
+	macro_rules! debug {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match impl Debug -> String { format!("{:?}", x) }
+				else -> String { String::from("<object>") }
+			}
+		};
+	}
+	assert_eq!(debug!(123), "123");
+	assert_eq!(debug!("abc"), "\"abc\"");
+	assert_eq!(debug!(NoDebugType), "<object>");
+
+	let result = spez! {
+		for x = (1u8, vec![2u8]);
+		match (impl Copy + Into<u32>, Vec<impl Copy>) -> u32 { x.0.into() + x.1.len() as u32 }
+		else -> u32 { 0 }
+	};
+	assert_eq!(result, 2);
+	let result = spez! {
+		for x = &5u8;
+		match<'a> &'a impl Clone -> Self { x }
+	};
+	assert_eq!(*result, 5);
+}
+
+#[test]
+fn user_defined_bounds() {
+	trait Integer {}
+	macro_rules! impl_integer {
+		($($t:ty)*) => { $(impl Integer for $t {})* };
+	}
+	impl_integer!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize);
+	macro_rules! kind {
+		($e:expr) => {
+			spez! {
+				for $e;
+				match<T: Integer> T -> &'static str { "integer" }
+				match f32 | f64 -> &'static str { "float" }
+				else -> &'static str { "other" }
+			}
+		};
+	}
+	assert_eq!(kind!(1u8), "integer");
+	assert_eq!(kind!(1i64), "integer");
+	assert_eq!(kind!(1usize), "integer");
+	assert_eq!(kind!(1.0f64), "float");
+	assert_eq!(kind!("1"), "other");
+}
+
+#[test]
+fn supertrait_bounds() {
+	use std::fmt::Debug;
+	trait Common: Debug + Clone {}
+	impl<T: Debug + Clone> Common for T {}
+	#[derive(Debug)]
+	struct NotClone;
+	macro_rules! describe {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match<T: Common> T -> String { format!("{:?}", x.clone()) }
+				match<T> T where T: Debug -> String { String::from("debug") }
+				else -> String { String::from("other") }
+			}
+		};
+	}
+	assert_eq!(describe!(1u8), "1");
+	assert_eq!(describe!(vec!["a"]), "[\"a\"]");
+	assert_eq!(describe!(NotClone), "debug");
+	assert_eq!(describe!(std::cell::Cell::new(1u8)), "Cell { value: 1 }");
+	assert_eq!(describe!(|| ()), "other");
+
+	let result = spez! {
+		for x = (1u8, "a");
+		match<A, B> (A, B) where A: Common, B: Common -> String { format!("{:?}", x) }
+		else -> String { String::from("other") }
+	};
+	assert_eq!(result, "(1, \"a\")");
+}
+
+#[test]
+fn generic_type_constructors() {
+	macro_rules! describe {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match i32 -> String { format!("i32 {}", x) }
+				match<T: std::fmt::Debug> Option<T> -> String {
+					match x {
+						Some(v) => format!("some {:?}", v),
+						None => String::from("none"),
+					}
+				}
+				match<T, E> Result<T, E> -> String {
+					String::from(if x.is_ok() { "ok" } else { "err" })
+				}
+				else -> String { String::from("other") }
+			}
+		};
+	}
+	assert_eq!(describe!(1i32), "i32 1");
+	assert_eq!(describe!(Some(1i32)), "some 1");
+	assert_eq!(describe!(None::<i32>), "none");
+	assert_eq!(describe!(Ok::<u8, ()>(1)), "ok");
+	assert_eq!(describe!(Err::<u8, ()>(())), "err");
+	assert_eq!(describe!("a"), "other");
+}
+
+#[test]
+fn not_bound() {
+	use std::fmt::Debug;
+	struct NoDebug;
+	macro_rules! describe {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match i32 -> &'static str { "i32" }
+				match<T> T where !Debug -> &'static str { "not debug" }
+				else -> &'static str { "debug" }
+			}
+		};
+	}
+	assert_eq!(describe!(1i32), "i32");
+	assert_eq!(describe!(NoDebug), "not debug");
+	assert_eq!(describe!("a"), "debug");
+
+	let result = spez! {
+		for x = 1u8;
+		match<T> T where !Clone -> u8 { 0 }
+		match<U> U -> U { x }
+	};
+	assert_eq!(result, 1);
+}
@@ -0,0 +1,573 @@
+use spez::spez;
+use spez::spez_ref;
+use std::cell::Cell;
+
+#[test]
+fn tuple_of_two() {
+	let a = 1u8;
+	let b = String::from("abc");
+	let result = spez! {
+		for (a, b);
+		match (u8, &str) -> usize { 0 }
+		match (u8, String) -> usize { a as usize + b.len() }
+	};
+	assert_eq!(result, 4);
+}
+
+#[test]
+fn tuple_of_three() {
+	let result = spez! {
+		for (a, b, c) = (1u8, 'x', [1, 2]);
+		match<T> (u8, char, T) -> u8 { 0 }
+		match _ -> u8 { 1 }
+	};
+	assert_eq!(result, 0);
+	let result = spez! {
+		for (a, b, c) = (1u8, 'x', [1u8, 2]);
+		match (u8, u8, [u8; 2]) -> u8 { 0 }
+		match (u8, char, [u8; 2]) -> u8 { a + c[1] + b as u8 }
+	};
+	assert_eq!(result, 3 + b'x');
+}
+
+#[test]
+fn nested_tuple() {
+	let result = spez! {
+		for (a, (b, c)) = (1u8, (2u8, &3u8));
+		match (u8, (u8, u8)) -> u8 { 0 }
+		match (u8, (u8, &u8)) -> u8 { a + b + c }
+	};
+	assert_eq!(result, 6);
+}
+
+#[test]
+fn block_input() {
+	fn compute() -> i32 {
+		-3
+	}
+	let result = spez! {
+		for x = {
+			let v = compute();
+			v.abs()
+		};
+		match i32 -> i32 { x }
+		match _ -> i32 { 0 }
+	};
+	assert_eq!(result, 3);
+	let result = spez! {
+		for {
+			let a = 1u8;
+			a + 1
+		};
+		match u8 -> i32 { 1 }
+		match _ -> i32 { 0 }
+	};
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn block_input_with_statements() {
+	fn f() -> u8 {
+		2
+	}
+	let result = spez! {
+		for x = {
+			let a = f();
+			let b = match a {
+				0 => 10,
+				n => n * 2,
+			};
+			if a > b {
+				a
+			} else {
+				a + b
+			}
+		} -> u8;
+		match u8 { x }
+		match i32 { 0 }
+		else { 1 }
+	};
+	assert_eq!(result, 6);
+}
+
+#[test]
+fn borrowed_input() {
+	let s = String::from("abc");
+	let f = || {
+		spez! {
+			for &s;
+			match String -> usize { s.len() }
+			match<T> T -> usize { 0 }
+		}
+	};
+	assert_eq!(f(), 3);
+	assert_eq!(f(), 3);
+	assert_eq!(s, "abc");
+
+	let x = 1u8;
+	let result = spez! {
+		for &x;
+		match u16 -> &'static str { "u16" }
+		match u8 -> &'static str { "u8" }
+	};
+	assert_eq!(result, "u8");
+	assert_eq!(x, 1);
+}
+
+#[test]
+fn mutably_borrowed_input() {
+	let mut v = vec![1];
+	spez! {
+		for &mut v;
+		match Vec<i32> { v.push(2); }
+		else {}
+	}
+	v.push(3);
+	assert_eq!(v, [1, 2, 3]);
+}
+
+#[test]
+fn mixed_mutable_and_shared_arms() {
+	trait Increment {
+		fn inc(&mut self);
+	}
+	impl Increment for u32 {
+		fn inc(&mut self) {
+			*self += 1;
+		}
+	}
+	macro_rules! bump {
+		($v:ident) => {
+			spez! {
+				for &mut $v;
+				match u32 -> &'static str { $v.inc(); "mutable" }
+				match<T> &T -> &'static str { let _: &T = $v; "shared" }
+			}
+		};
+	}
+	let mut a = 1u32;
+	let mut b = "a";
+	assert_eq!(bump!(a), "mutable");
+	assert_eq!(bump!(b), "shared");
+	assert_eq!((a, b), (2, "a"));
+
+	let mut v = 10u8;
+	let r = spez! {
+		for &mut v;
+		match &u8 if *v > 5 -> u8 { *v }
+		match u8 -> u8 { *v += 1; *v }
+	};
+	assert_eq!(r, 10);
+	let r = spez! {
+		for &mut v;
+		match &u8 -> Self => v
+	};
+	assert_eq!(*r, 10);
+}
+
+#[test]
+fn mutable_reference_and_by_value_arms() {
+	struct MyStruct(u32);
+	macro_rules! bump {
+		($e:expr) => {
+			spez! {
+				for x = $e;
+				match &mut MyStruct -> u32 { x.0 += 1; x.0 }
+				match &MyStruct -> u32 { x.0 + 10 }
+				match MyStruct -> u32 { x.0 + 20 }
+				match u32 -> u32 { x + 100 }
+				else -> u32 { 0 }
+			}
+		};
+	}
+	let mut s = MyStruct(1);
+	assert_eq!(bump!(&mut s), 2);
+	assert_eq!(bump!(&mut s), 3);
+	assert_eq!(bump!(&s), 13);
+	assert_eq!(bump!(MyStruct(7)), 27);
+	assert_eq!(bump!(1u32), 101);
+	assert_eq!(bump!(&1u32), 0);
+	assert_eq!(s.0, 3);
+}
+
+#[test]
+fn mutable_reference_through_variable() {
+	struct MyStruct1(u32);
+	struct MyStruct2(u32);
+
+	trait Increment {
+		fn inc(&mut self);
+	}
+
+	impl Increment for &mut MyStruct1 {
+		fn inc(&mut self) {
+			self.0 += 1;
+		}
+	}
+
+	let mut my_object1 = MyStruct1(0);
+	let mut my_object2 = MyStruct2(0);
+
+	let r = &mut my_object1;
+	spez! {
+		for r;
+		match<T> T where T: Increment { r.inc(); }
+		match<T> T {}
+	}
+	let r = &mut my_object2;
+	spez! {
+		for r;
+		match<T> T where T: Increment { r.inc(); }
+		match<T> T {}
+	}
+
+	assert_eq!(my_object1.0, 1);
+	assert_eq!(my_object2.0, 0);
+}
+
+#[test]
+#[deny(unused_mut)]
+fn mutable_binding() {
+	let result = spez! {
+		for x = 1;
+		match i32 -> i32 {
+			x += 1;
+			x
+		}
+		match i64 -> i32 { 0 }
+		else -> i32 { 0 }
+	};
+	assert_eq!(result, 2);
+
+	let result = spez! {
+		for x = String::from("a");
+		match String -> String {
+			x.push('b');
+			x
+		}
+	};
+	assert_eq!(result, "ab");
+}
+
+#[test]
+fn ref_binding() {
+	let s = String::from("abc");
+	let len = spez! {
+		for ref x = s;
+		match String -> usize { x.len() }
+		else -> usize { 0 }
+	};
+	assert_eq!(len, 3);
+	assert_eq!(s, "abc");
+
+	let words = [String::from("a"), String::from("bc")];
+	let len = spez! {
+		for ref x = words[1];
+		match String -> usize { x.len() }
+		else -> usize { 0 }
+	};
+	assert_eq!(len, 2);
+	assert_eq!(words.len(), 2);
+}
+
+#[test]
+fn ref_mut_binding() {
+	let mut v = vec![1];
+	spez! {
+		for ref mut x = v;
+		match Vec<i32> { x.push(2); }
+		else {}
+	}
+	spez! {
+		for ref mut v;
+		match Vec<i32> { v.push(3); }
+		else {}
+	}
+	assert_eq!(v, [1, 2, 3]);
+}
+
+struct CountDrops<'a>(&'a Cell<u32>);
+
+impl Drop for CountDrops<'_> {
+	fn drop(&mut self) {
+		self.0.set(self.0.get() + 1);
+	}
+}
+
+#[test]
+fn shadowed_input() {
+	let result = spez! {
+		for x = 5i32;
+		match i32 -> i32 { let x = 10i32; x + 1 }
+	};
+	assert_eq!(result, 11);
+	let result = spez! {
+		for x = 5i32;
+		match i32 if x > 0 -> i32 { let x = x * 2; let x = x + 1; x * 2 }
+		else -> i32 { 0 }
+	};
+	assert_eq!(result, 22);
+	let result = spez! {
+		for x = String::from("abc");
+		match String -> usize { let x = x.len(); x + 1 }
+	};
+	assert_eq!(result, 4);
+	let x = 1i32;
+	let result = spez! {
+		for x = x + 1;
+		match i32 -> i32 { x }
+	};
+	assert_eq!(result, 2);
+	assert_eq!(x, 1);
+}
+
+#[test]
+fn dropped_once() {
+	let drops = Cell::new(0);
+	let result = spez! {
+		for x = CountDrops(&drops);
+		match i32 -> u32 { 0 }
+		match CountDrops<'_> -> u32 {
+			let drops = x.0;
+			drop(x);
+			drops.get()
+		}
+	};
+	assert_eq!(result, 1);
+	assert_eq!(drops.get(), 1);
+
+	let drops = Cell::new(0);
+	let x = spez! {
+		for x = CountDrops(&drops);
+		match<'a> CountDrops<'a> if false -> CountDrops<'a> { x }
+		match<'a> CountDrops<'a> -> CountDrops<'a> { x }
+	};
+	assert_eq!(drops.get(), 0);
+	drop(x);
+	assert_eq!(drops.get(), 1);
+}
+
+#[test]
+fn unused_input_is_dropped_at_the_end() {
+	let drops = &Cell::new(0);
+	let during = spez! {
+		for CountDrops(drops);
+		capture(drops: &Cell<u32>);
+		match i32 -> u32 { 0 }
+		match CountDrops<'_> -> u32 { drops.get() }
+	};
+	assert_eq!(during, 0);
+	assert_eq!(drops.get(), 1);
+
+	let drops = &Cell::new(0);
+	let during = spez! {
+		for x = CountDrops(drops);
+		capture(drops: &Cell<u32>);
+		match CountDrops<'_> if drops.get() > 0 -> u32 { 0 }
+		match (i32, i32) -> u32 { 1 }
+		else -> u32 { drops.get() }
+	};
+	assert_eq!(during, 0);
+	assert_eq!(drops.get(), 1);
+}
+
+#[test]
+fn spez_ref() {
+	let v = vec![1i32, 2, 3];
+	let describe = || {
+		spez_ref! {
+			for x = v;
+			match Vec<u8> -> usize { 0 }
+			match Vec<i32> -> usize { x.len() }
+		}
+	};
+	assert_eq!(describe(), 3);
+	assert_eq!(describe(), 3);
+	let first = spez_ref! {
+		for v[0];
+		match i32 -> bool { true }
+		else -> bool { false }
+	};
+	assert!(first);
+	assert_eq!(v, [1, 2, 3]);
+
+	// An input that is already borrowed is not borrowed again.
+	let len = spez_ref! {
+		for &v;
+		match Vec<i32> -> usize { v.len() }
+		else -> usize { 0 }
+	};
+	assert_eq!(len, 3);
+
+	// The names in a tuple pattern are bound to references to the elements.
+	let pair = (String::from("a"), vec![1i32, 2]);
+	let len = spez_ref! {
+		for (s, v) = pair;
+		match (String, Vec<i32>) -> usize { s.len() + v.len() }
+		else -> usize { 0 }
+	};
+	assert_eq!(len, 3);
+	assert_eq!(pair.0, "a");
+}
+
+#[test]
+fn unused_borrowed_input() {
+	let mut v = vec![1i32, 2, 3];
+	let len = spez! {
+		for &mut v;
+		match Vec<u8> -> usize { 0 }
+		match Vec<i32> -> usize { 100 }
+	};
+	assert_eq!(len, 100);
+	v.push(4);
+	assert_eq!(v, [1, 2, 3, 4]);
+
+	let s = String::from("abc");
+	let first = spez_ref! {
+		for x = s;
+		match String -> &'static str { "string" }
+		else -> &'static str { "other" }
+	};
+	assert_eq!(first, "string");
+	assert_eq!(s, "abc");
+
+	// A name used only in a format string still counts as used.
+	let debug = spez_ref! {
+		for v;
+		match Vec<i32> -> String { format!("{v:?}") }
+	};
+	assert_eq!(debug, "[1, 2, 3, 4]");
+}
+
+#[test]
+fn raw_identifiers() {
+	let result = spez! {
+		for r#type = 5i32;
+		match i32 -> i32 { r#type + 1 }
+	};
+	assert_eq!(result, 6);
+	let result = spez! {
+		for 5i32;
+		match i32 as r#match -> i32 { r#match + 2 }
+	};
+	assert_eq!(result, 7);
+	let result = spez! {
+		for (r#type, r#fn) = (1i32, 2i32);
+		match (i32, i32) if r#type > 5 -> Self { (0, 0) }
+		match (i32, i32) -> Self { self }
+	};
+	assert_eq!(result, (1, 2));
+	let r#type = 3i32;
+	let r#fn = 1i32;
+	let result = spez! {
+		for &r#type;
+		capture(r#fn: i32);
+		match i32 -> i32 { *r#type + r#fn }
+	};
+	assert_eq!(result, 4);
+}
+
+#[test]
+fn each_input() {
+	let s = String::from("abc");
+	let extra = 10usize;
+	let result = spez! {
+		for x in [5u8, "abcd", s, 7u8] -> usize;
+		capture(extra: usize);
+		match u8 if x > 6 { x as usize + extra }
+		match u8 { x as usize }
+		match &str { x.len() }
+		else { 0 }
+	};
+	assert_eq!(result, [5, 4, 0, 17]);
+
+	// Anything that is not `Copy` is shared through a reference.
+	let suffix = String::from("!");
+	let name = &suffix;
+	let result = spez! {
+		for x in [1u8, 2u8] -> String;
+		capture(name: &String);
+		match u8 { format!("{x}{name}") }
+	};
+	assert_eq!(result, ["1!", "2!"]);
+
+	let v = vec![1i32, 2, 3];
+	let lengths = spez_ref! {
+		for v in [v, vec![4i32]] -> usize;
+		match Vec<i32> { v.len() }
+	};
+	assert_eq!(lengths, [3, 1]);
+	assert_eq!(v, [1, 2, 3]);
+
+	let result = spez! {
+		for (a, b) in [(1i32, 2i32), (3i32, 4i32)] -> i32;
+		match (i32, i32) { a + b }
+	};
+	assert_eq!(result, [3, 7]);
+}
+
+#[test]
+fn nested() {
+	let result = spez! {
+		for x = String::from("abc");
+		match String -> usize {
+			let inner = spez! {
+				for &x;
+				match String -> usize { x.len() }
+				else -> usize { 0 }
+			};
+			inner + x.len()
+		}
+	};
+	assert_eq!(result, 6);
+
+	let v = vec![1i32, 2];
+	let result = spez! {
+		for &v;
+		match Vec<i32> -> usize {
+			spez! {
+				for v;
+				match &Vec<u8> -> usize { 0 }
+				match &Vec<i32> -> usize {
+					v.len() + spez_ref! {
+						for x = v[0];
+						match i32 -> usize { *x as usize }
+					}
+				}
+			}
+		}
+		else -> usize { 9 }
+	};
+	assert_eq!(result, 3);
+	assert_eq!(v, [1, 2]);
+
+	let mut v = vec![1i32];
+	let result = spez! {
+		for &mut v;
+		match Vec<i32> -> usize {
+			spez! {
+				for ref mut v = *v;
+				match Vec<i32> -> usize { v.push(2); v.len() }
+			};
+			spez! {
+				for v;
+				match &mut Vec<i32> -> usize { v.push(3); v.len() }
+			}
+		}
+	};
+	assert_eq!(result, 3);
+	assert_eq!(v, [1, 2, 3]);
+
+	// In a generic arm, the inner `spez!` only sees the bounds.
+	let result = spez! {
+		for x = 1i32;
+		match<T: Copy> T -> &'static str {
+			spez! {
+				for &x;
+				match i32 -> &'static str { "i32" }
+				else -> &'static str { "other" }
+			}
+		}
+	};
+	assert_eq!(result, "other");
+}
@@ -0,0 +1,204 @@
+//! The code generated by the macros should not trigger any warnings, even with
+//! lints that are allowed by default.
+
+#![deny(warnings)]
+#![warn(clippy::pedantic, clippy::nursery)]
+#![warn(
+	elided_lifetimes_in_paths,
+	let_underscore_drop,
+	single_use_lifetimes,
+	trivial_casts,
+	unit_bindings,
+	unreachable_pub,
+	unsafe_code,
+	unused_lifetimes,
+	unused_qualifications,
+	unused_results
+)]
+
+use spez::spez;
+use spez::spez_fn;
+use spez::spez_matches;
+use spez::spez_with_index;
+use spez::try_clone;
+use std::fmt::Debug;
+
+#[test]
+fn arms() {
+	let v = 1;
+	let result = spez! {
+		for x = v;
+		match<T: Debug> T -> String { format!("{x:?}") }
+		match i32 -> String { String::new() }
+		else -> String { String::new() }
+	};
+	assert_eq!(result, "1");
+
+	let a = 1;
+	let result = spez! {
+		for (p, q) = (1_u8, 2_u8);
+		capture(a: i32);
+		prelude {
+			let unused = 1;
+		}
+		match (u8, u8) if p > 100 -> (u8, u8) { (q, p) }
+		#[cfg(all())]
+		match (u8, u8) -> Self => self
+		match<T> T where !Clone -> (u8, u8) { (0, 0) }
+		else -> (u8, u8) { (u8::from(a > 0), 0) }
+	};
+	assert_eq!(result, (1, 2));
+
+	let result = spez! {
+		for x = 3_u8;
+		match u8 -> impl Iterator<Item = u8> { 0..x }
+		match i32 -> ! { panic!() }
+		else -> () {}
+	};
+	assert_eq!(result.count(), 3);
+
+	let result = spez! {
+		for x = 2_u8;
+		#[deny(unused_variables)]
+		match u8 -> u8 { x }
+		match u16 -> u8 { #![warn(unused)] u8::try_from(x).unwrap_or(0) }
+		else -> u8 { 0 }
+	};
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn borrowed_input() {
+	let s = String::from("a");
+	let len = spez! {
+		for &s;
+		match String -> usize { s.len() }
+		match &str if s.is_empty() -> usize { 0 }
+		else -> usize { 0 }
+	};
+	assert_eq!(len, 1);
+}
+
+#[test]
+fn shadowed_input() {
+	let result = spez! {
+		for x = 2_u8;
+		match u8 -> u8 { let x = 3_u8; x + 1 }
+		match u16 -> u8 { let x = u8::try_from(x).unwrap_or(0); x * 2 }
+		else -> u8 { 0 }
+	};
+	assert_eq!(result, 4);
+}
+
+#[test]
+fn use_trait() {
+	trait Double {
+		fn spez(self) -> u8;
+	}
+	impl Double for u8 {
+		fn spez(self) -> u8 {
+			self * 2
+		}
+	}
+	let result = spez! {
+		for x = 2_u8;
+		use trait Double -> u8;
+		else -> u8 { 0 }
+	};
+	assert_eq!(result, 4);
+}
+
+#[test]
+fn type_aliases() {
+	let result = spez! {
+		for x = 2_u8;
+		type A = u8;
+		type B = u16;
+		match A -> A { x }
+		match B -> A { 0 }
+		else -> A { 0 }
+	};
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn each_input() {
+	let result = spez! {
+		for x in [1_u8, 2_u8] -> u8;
+		match u8 { x }
+		else { 0 }
+	};
+	assert_eq!(result, [1, 2]);
+}
+
+#[test]
+fn nested() {
+	let result = spez! {
+		for x = 1_u8;
+		match u8 -> u8 {
+			spez! {
+				for &x;
+				match u8 -> u8 { *x + 1 }
+				else -> u8 { 0 }
+			}
+		}
+	};
+	assert_eq!(result, 2);
+}
+
+#[derive(Debug, PartialEq, Eq, spez::Spez)]
+enum Payload {
+	Number(u8),
+	Text(String),
+}
+
+#[test]
+fn derive() {
+	assert_eq!(payload!(1_u8), Some(Payload::Number(1)));
+	assert_eq!(payload!(String::new()), Some(Payload::Text(String::new())));
+	assert_eq!(payload!(()), None);
+}
+
+#[test]
+fn async_bodies() {
+	let future = spez! {
+		async for x = 1_u8;
+		match u8 -> u8 { x }
+		else -> u8 { 0 }
+	};
+	drop(future);
+}
+
+#[test]
+fn deferred_bodies() {
+	let f = spez_fn! {
+		for x = 1_u8;
+		match u8 if x > 0 -> u8 { x }
+		else -> u8 { 0 }
+	};
+	assert_eq!(f(), 1);
+
+	let f = spez_fn! {
+		async for x = 1_u8;
+		match u8 -> u8 { x }
+		else -> u8 { 0 }
+	};
+	drop(f());
+}
+
+#[test]
+fn helpers() {
+	assert_eq!(
+		spez_with_index! {
+			for x = 1_u8;
+			match u8 -> u8 { x }
+			else -> u8 => 0
+		},
+		(1, 0)
+	);
+	#[cfg(feature = "std")]
+	assert_eq!(spez::spez_type_name!(1), "1");
+	assert_eq!(try_clone!(1), Some(1));
+	assert!(spez_matches!(1, Copy));
+	spez::spez_assert_impl!(1, Copy);
+}
@@ -0,0 +1,109 @@
+use spez::spez_all_matches;
+use spez::spez_assert_impl;
+use spez::spez_matches;
+#[cfg(feature = "std")]
+use spez::spez_type_name;
+use spez::try_clone;
+use std::fmt::Debug;
+
+#[test]
+fn debug() {
+	struct NoDebug;
+	assert!(spez_matches!(1, Debug));
+	assert!(spez_matches!("a", Debug));
+	assert!(!spez_matches!(NoDebug, Debug));
+}
+
+#[test]
+fn custom_trait() {
+	trait Special {}
+	struct A;
+	struct B;
+	impl Special for A {}
+	assert!(spez_matches!(A, Special));
+	assert!(!spez_matches!(B, Special));
+}
+
+#[test]
+fn multiple_bounds() {
+	assert!(spez_matches!(1u8, Copy + Into<u32>));
+	assert!(!spez_matches!(1u32, Copy + Into<u8>));
+	assert!(spez_matches!(String::new(), Clone + Default,));
+}
+
+#[test]
+fn not_consumed() {
+	let v = vec![1, 2, 3];
+	assert!(spez_matches!(v, Clone));
+	assert!(!spez_matches!(v, Copy));
+	assert_eq!(v, [1, 2, 3]);
+}
+
+#[test]
+fn all_matches() {
+	use std::fmt::Display;
+	trait Special {}
+	#[derive(Debug, Clone)]
+	struct A;
+	impl Special for A {}
+	assert_eq!(
+		spez_all_matches!(A, [Debug, Clone, Special, Copy]),
+		[true, true, true, false]
+	);
+	assert_eq!(
+		spez_all_matches!(1u8, [Debug, Display, Copy + Into<u32>, Special,]),
+		[true, true, true, false]
+	);
+	assert_eq!(spez_all_matches!("a", [Special]), [false]);
+}
+
+#[test]
+fn all_matches_evaluated_once() {
+	let mut count = 0;
+	let v = vec![1, 2];
+	let matches = spez_all_matches!(
+		{
+			count += 1;
+			&v
+		},
+		[Clone, Copy, Debug]
+	);
+	assert_eq!(matches, [true, true, true]);
+	assert_eq!(count, 1);
+	assert_eq!(v, [1, 2]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn type_name() {
+	struct NoDebug;
+	let s = String::from("abc");
+	assert_eq!(spez_type_name!(s), "\"abc\"");
+	assert_eq!(spez_type_name!(1 + 1), "2");
+	assert!(spez_type_name!(NoDebug).ends_with("NoDebug"));
+	assert_eq!(s, "abc");
+}
+
+#[test]
+fn try_clone() {
+	struct NoClone;
+	let v = vec![1, 2];
+	let c: Option<Vec<i32>> = try_clone!(v);
+	assert_eq!(c, Some(vec![1, 2]));
+	assert_eq!(v, [1, 2]);
+	assert!(try_clone!(NoClone).is_none());
+	assert_eq!(try_clone!(&5), Some(&5));
+}
+
+#[test]
+fn assert_impl() {
+	let s = String::from("abc");
+	spez_assert_impl!(s, Debug + Clone);
+	spez_assert_impl!(1u8, Copy + Into<u32>,);
+	assert_eq!(s, "abc");
+
+	// The expression is not evaluated.
+	let evaluated = std::cell::Cell::new(false);
+	spez_assert_impl!(evaluated.replace(true), Debug);
+	assert!(!evaluated.get());
+}
@@ -0,0 +1,89 @@
+//! `SPEZ_MAX_ARMS` is read when the macros run, so these tests build a small
+//! crate with it set.
+
+mod common;
+
+/// Builds a crate with `source` as its `lib.rs` and `SPEZ_MAX_ARMS` set, and
+/// returns the errors.
+fn build(max_arms: &str, source: &str) -> Result<(), String> {
+	common::build("max_arms", &[("SPEZ_MAX_ARMS", max_arms)], source)
+}
+
+#[test]
+fn max_arms() {
+	let three = "pub fn f() -> u8 {
+		spez::spez! {
+			for 1u8;
+			match u8 -> u8 { 1 }
+			match u16 -> u8 { 2 }
+			else -> u8 { 0 }
+		}
+	}";
+	assert_eq!(build("3", three), Ok(()));
+
+	let four = "pub fn f() -> u8 {
+		spez::spez! {
+			for 1u8;
+			match u8 -> u8 { 1 }
+			match u16 -> u8 { 2 }
+			match u32 -> u8 { 3 }
+			else -> u8 { 0 }
+		}
+	}";
+	let error = build("3", four).unwrap_err();
+	assert!(
+		error.contains("this spez! has 4 arms, more than the 3 allowed by `SPEZ_MAX_ARMS`"),
+		"{}",
+		error
+	);
+	// The error points at the `else`, which is the fourth arm.
+	assert!(error.contains("lib.rs:7:"), "{}", error);
+
+	// The arms are counted in the order they are written, before sorting them
+	// by priority.
+	let priority = "pub fn f() -> u8 {
+		spez::spez! {
+			for 1u8;
+			match u8 -> u8 { 1 }
+			match(priority = 0) u16 -> u8 { 2 }
+		}
+	}";
+	let error = build("1", priority).unwrap_err();
+	assert!(error.contains("lib.rs:5:"), "{}", error);
+
+	// Arms added by the macros themselves are not counted.
+	let helpers = "#[derive(Clone)]
+	pub struct S;
+	pub fn f() -> bool {
+		let _ = spez::try_clone!(S);
+		let _ = spez::spez_all_matches!(S, [Clone, Copy]);
+		let _: u8 = spez::spez! {
+			#[spez(panic_default)]
+			for 1u8;
+			match u8 -> u8 { 1 }
+		};
+		spez::spez_matches!(S, Clone)
+	}";
+	assert_eq!(build("1", helpers), Ok(()));
+
+	// The macro of `#[derive(Spez)]` has an arm for each variant, and an
+	// `else` arm, and these are counted.
+	let derive = "#[derive(spez::Spez)]
+	pub enum E {
+		A(u8),
+		B(u16),
+	}
+	pub fn f() -> Option<E> {
+		e!(1u8)
+	}";
+	assert_eq!(build("3", derive), Ok(()));
+	let error = build("2", derive).unwrap_err();
+	assert!(error.contains("this spez! has 3 arms"), "{}", error);
+
+	let error = build("x", three).unwrap_err();
+	assert!(
+		error.contains("`SPEZ_MAX_ARMS` must be a number, not `x`"),
+		"{}",
+		error
+	);
+}
@@ -0,0 +1,61 @@
+//! The code generated by the macros should only use `core`.
+
+#![no_std]
+
+// The test harness needs `std`. Under another name, the generated code can't
+// refer to it.
+extern crate std as test_std;
+
+use core::cell::Cell;
+use core::fmt::Debug;
+use spez::spez;
+use spez::spez_fn;
+use spez::spez_matches;
+use spez::spez_ref;
+use spez::spez_with_index;
+use spez::try_clone;
+
+#[test]
+fn arms() {
+	let result = spez! {
+		for x = 1_u8;
+		match u8 if x > 10 -> u8 { 10 }
+		match u8 | u16 -> Self => self
+		match<T: Debug> T -> u8 { 0 }
+		match<T> T where !Clone -> u8 { 0 }
+		else -> u8 { 0 }
+	};
+	assert_eq!(result, 1);
+
+	let calls = Cell::new(0);
+	let result = spez_ref! {
+		for x = calls;
+		match Cell<i32> -> i32 { x.set(x.get() + 1); x.get() }
+	};
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn other_macros() {
+	let future = spez! {
+		async for x = 2_u8;
+		match u8 -> u8 { x }
+	};
+	drop(future);
+	let f = spez_fn! {
+		for x = 2_u8;
+		match u8 -> u8 { x }
+	};
+	assert_eq!(f(), 2);
+	assert_eq!(
+		spez_with_index! {
+			for 1_u8;
+			match u16 {}
+			else {}
+		},
+		((), 1)
+	);
+	assert_eq!(try_clone!(1_u8), Some(1));
+	assert!(spez_matches!(1_u8, Copy));
+	spez::spez_assert_impl!(1_u8, Copy);
+}
@@ -0,0 +1,24 @@
+use spez::spez;
+
+#[allow(dead_code)]
+mod core {}
+
+#[test]
+fn shadowed_core() {
+	let result = spez! {
+		for x = 1u8;
+		match u8 -> u8 { x }
+		else -> u8 { 0 }
+	};
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn shadowed_core_async() {
+	let future = spez! {
+		async for x = 1u8;
+		match u8 -> u8 { x }
+		else -> u8 { 0 }
+	};
+	drop(future);
+}